@@ -1,19 +1,50 @@
+use std::collections::HashSet;
 use std::fmt::Display;
 
 pub struct IniBuilder {
     output: String,
+    keys: HashSet<String>,
+    section: String,
+    entries: Vec<(String, String, String)>,
 }
 
 impl IniBuilder {
     pub fn add_section(&mut self, section: &str) {
+        self.section = section.to_string();
         self.output.push_str(&format!("[{}]\n", section));
     }
 
     pub fn add_setting(&mut self, key: &str, value: impl Display) {
+        let value = value.to_string();
+        self.keys.insert(key.to_string());
+        self.entries.push((self.section.clone(), key.to_string(), value.clone()));
         self.output.push_str(&format!("{} = {}\n", key, value));
     }
 
-    pub fn add_comma_separated(&mut self, key: &str, values: &Vec<impl Display>) {
+    pub fn add_optional_setting(&mut self, key: &str, value: Option<impl Display>) {
+        if let Some(value) = value {
+            self.add_setting(key, value);
+        }
+    }
+
+    /// Booleans are written as `1` and `0`.
+    pub fn add_optional_bool(&mut self, key: &str, value: Option<bool>) {
+        self.add_optional_setting(key, value.map(u8::from));
+    }
+
+    /// If the setting has been added in any section.
+    pub fn has_setting(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// The settings added to a section, in the order they were added.
+    pub fn settings<'a>(&'a self, section: &'a str) -> impl Iterator<Item=(&'a str, &'a str)> + 'a {
+        self.entries.iter()
+            .filter(move |(s, _, _)| s == section)
+            .map(|(_, key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn add_comma_separated(&mut self, key: &str, values: &[impl Display]) {
         if values.is_empty() {
             return;
         }
@@ -40,6 +71,9 @@ impl IniBuilder {
 pub fn new() -> IniBuilder {
     IniBuilder {
         output: String::new(),
+        keys: HashSet::new(),
+        section: String::new(),
+        entries: Vec::new(),
     }
 }
 
@@ -58,6 +92,10 @@ mod tests {
 
         builder.add_comma_separated("comma_separated", &vec_values);
 
-        assert_eq!(builder.output, "[pgbouncer]\npool_mode = transaction\nlisten_port = 5432\ncomma_separated = one, two, three\n");
+        builder.add_section("databases");
+        builder.add_setting("db", "host=localhost");
+
+        assert_eq!(builder.settings("pgbouncer").collect::<Vec<_>>(), vec![("pool_mode", "transaction"), ("listen_port", "5432"), ("comma_separated", "one, two, three")]);
+        assert_eq!(builder.output, "[pgbouncer]\npool_mode = transaction\nlisten_port = 5432\ncomma_separated = one, two, three\n[databases]\ndb = host=localhost\n");
     }
 }
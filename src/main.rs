@@ -10,7 +10,9 @@ extern crate log;
 use clap::Parser;
 use futures::stream::StreamExt;
 use k8s_openapi::api::apps::v1::Deployment;
-use k8s_openapi::api::core::v1::{ConfigMap, Service};
+use k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret, Service};
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
 use kube::client::Client;
 use kube::{Api, CustomResourceExt, Resource};
 use kube_runtime::controller::{Action};
@@ -23,7 +25,7 @@ use std::io::Write;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinSet;
-use crate::types::{HasPgBouncerReference, PgBouncer, PgBouncerDatabase, PgBouncerUser, PostgresAdminConnection, PostgresRole, PostgresSchema};
+use crate::types::{HasPgBouncerReference, PgBouncer, PgBouncerDatabase, PgBouncerUser, PostgresAdminConnection, PostgresRole, PostgresSchema, PostgresTenant, PostgresPublication, PostgresSubscription, PostgresReplicationSlot, PostgresForeignServer, PostgresUserMapping, PostgresCronJob, PostgresPolicy, PostgresSqlJob, PostgresDatabaseSettings, PostgresQueryGuard, PostgresMaintenance};
 
 #[derive(Parser, Debug)]
 #[command(long_about = None)]
@@ -56,10 +58,25 @@ async fn main() -> anyhow::Result<()> {
     let related_pg_bouncer_users_api: Api<PgBouncerUser> = Api::all(kubernetes_client.clone());
     let postgres_roles_api: Api<PostgresRole> = Api::all(kubernetes_client.clone());
     let postgres_schemas_api: Api<PostgresSchema> = Api::all(kubernetes_client.clone());
+    let postgres_tenants_api: Api<PostgresTenant> = Api::all(kubernetes_client.clone());
+    let postgres_publications_api: Api<PostgresPublication> = Api::all(kubernetes_client.clone());
+    let postgres_subscriptions_api: Api<PostgresSubscription> = Api::all(kubernetes_client.clone());
+    let postgres_replication_slots_api: Api<PostgresReplicationSlot> = Api::all(kubernetes_client.clone());
+    let postgres_foreign_servers_api: Api<PostgresForeignServer> = Api::all(kubernetes_client.clone());
+    let postgres_user_mappings_api: Api<PostgresUserMapping> = Api::all(kubernetes_client.clone());
+    let postgres_cron_jobs_api: Api<PostgresCronJob> = Api::all(kubernetes_client.clone());
+    let postgres_policies_api: Api<PostgresPolicy> = Api::all(kubernetes_client.clone());
+    let postgres_sql_jobs_api: Api<PostgresSqlJob> = Api::all(kubernetes_client.clone());
+    let postgres_database_settings_api: Api<PostgresDatabaseSettings> = Api::all(kubernetes_client.clone());
+    let postgres_query_guard_api: Api<PostgresQueryGuard> = Api::all(kubernetes_client.clone());
+    let postgres_maintenance_api: Api<PostgresMaintenance> = Api::all(kubernetes_client.clone());
 
     let deployments_api: Api<Deployment> = Api::all(kubernetes_client.clone());
     let services_api: Api<Service> = Api::all(kubernetes_client.clone());
     let config_map_api: Api<ConfigMap> = Api::all(kubernetes_client.clone());
+    let secrets_api: Api<Secret> = Api::all(kubernetes_client.clone());
+    let autoscalers_api: Api<HorizontalPodAutoscaler> = Api::all(kubernetes_client.clone());
+    let pod_disruption_budgets_api: Api<PodDisruptionBudget> = Api::all(kubernetes_client.clone());
 
     let context = Arc::new(ContextData {
         kubernetes_client: kubernetes_client.clone(),
@@ -73,6 +90,9 @@ async fn main() -> anyhow::Result<()> {
         .owns(deployments_api, Config::default().labels("controller-watcher=postgres-topology-operator"))
         .owns(services_api, Config::default().labels("controller-watcher=postgres-topology-operator"))
         .owns(config_map_api, Config::default().labels("controller-watcher=postgres-topology-operator"))
+        .owns(secrets_api.clone(), Config::default().labels("controller-watcher=postgres-topology-operator"))
+        .owns(autoscalers_api, Config::default().labels("controller-watcher=postgres-topology-operator"))
+        .owns(pod_disruption_budgets_api, Config::default().labels("controller-watcher=postgres-topology-operator"))
         .run(reconcilers::pg_bouncer::reconcile_pg_bouncer, error_policy, context.clone())
         .for_each(|res| async move {
             match res {
@@ -100,6 +120,117 @@ async fn main() -> anyhow::Result<()> {
             }
         }));
 
+    tasks.spawn(Controller::new(postgres_tenants_api.clone(), Config::default())
+        .owns(postgres_roles_api, Config::default())
+        .owns(postgres_schemas_api, Config::default())
+        .owns(secrets_api, Config::default().labels("controller-watcher=postgres-topology-operator"))
+        .run(reconcilers::postgres_tenant::reconcile_postgres_tenant, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_publications_api.clone(), Config::default())
+        .run(reconcilers::postgres_publication::reconcile_postgres_publication, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_subscriptions_api.clone(), Config::default())
+        .run(reconcilers::postgres_subscription::reconcile_postgres_subscription, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_replication_slots_api.clone(), Config::default())
+        .run(reconcilers::postgres_replication_slot::reconcile_postgres_replication_slot, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_foreign_servers_api.clone(), Config::default())
+        .run(reconcilers::postgres_foreign_server::reconcile_postgres_foreign_server, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_user_mappings_api.clone(), Config::default())
+        .run(reconcilers::postgres_user_mapping::reconcile_postgres_user_mapping, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_cron_jobs_api.clone(), Config::default())
+        .run(reconcilers::postgres_cron_job::reconcile_postgres_cron_job, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_policies_api.clone(), Config::default())
+        .run(reconcilers::postgres_policy::reconcile_postgres_policy, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_sql_jobs_api.clone(), Config::default())
+        .run(reconcilers::postgres_sql_job::reconcile_postgres_sql_job, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_database_settings_api.clone(), Config::default())
+        .run(reconcilers::postgres_database_settings::reconcile_postgres_database_settings, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_query_guard_api.clone(), Config::default())
+        .run(reconcilers::postgres_query_guard::reconcile_postgres_query_guard, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
+    tasks.spawn(Controller::new(postgres_maintenance_api.clone(), Config::default())
+        .run(reconcilers::postgres_maintenance::reconcile_postgres_maintenance, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => debug!("reconciled: {:?}", o),
+                Err(e) => error!("reconcile failed: {:?}", e),
+            }
+        }));
+
     info!("Operator tasks started");
 
     while let Some(res) = tasks.join_next().await {
@@ -121,6 +252,18 @@ fn write_crds() -> anyhow::Result<()> {
     write_crd::<PgBouncer>(&mut file)?;
     write_crd::<PgBouncerUser>(&mut file)?;
     write_crd::<PgBouncerDatabase>(&mut file)?;
+    write_crd::<PostgresTenant>(&mut file)?;
+    write_crd::<PostgresPublication>(&mut file)?;
+    write_crd::<PostgresSubscription>(&mut file)?;
+    write_crd::<PostgresReplicationSlot>(&mut file)?;
+    write_crd::<PostgresForeignServer>(&mut file)?;
+    write_crd::<PostgresUserMapping>(&mut file)?;
+    write_crd::<PostgresCronJob>(&mut file)?;
+    write_crd::<PostgresPolicy>(&mut file)?;
+    write_crd::<PostgresSqlJob>(&mut file)?;
+    write_crd::<PostgresDatabaseSettings>(&mut file)?;
+    write_crd::<PostgresQueryGuard>(&mut file)?;
+    write_crd::<PostgresMaintenance>(&mut file)?;
 
     Ok(())
 }
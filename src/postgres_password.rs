@@ -41,6 +41,17 @@ impl PostgresPassword {
         }
     }
 
+    /// The plaintext password, or None when the password is given already hashed.
+    pub fn get_plaintext(&self) -> Option<&str> {
+        match self {
+            PostgresPassword::Plain(s) => Some(s),
+            PostgresPassword::Md5(s) if s.starts_with("md5") => None,
+            PostgresPassword::Md5(s) => Some(s),
+            PostgresPassword::ScramSha256(s) if s.starts_with("SCRAM-SHA-256$") => None,
+            PostgresPassword::ScramSha256(s) => Some(s),
+        }
+    }
+
     pub fn with_new_text(&self, text: String) -> Self {
         match self {
             PostgresPassword::Plain(_) => PostgresPassword::Plain(text),
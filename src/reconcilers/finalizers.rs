@@ -47,7 +47,7 @@ where
         .meta()
         .finalizers
         .as_ref()
-        .map_or(true, |finalizers| finalizers.is_empty())
+        .is_none_or(|finalizers| finalizers.is_empty())
     {
         debug!("Finalizer not found on resource {namespace}/{name}, adding");
         let resource = add_finalizer::<TResource>(client, &name, &namespace).await?;
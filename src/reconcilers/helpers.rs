@@ -1,28 +1,41 @@
+use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
 use anyhow::{bail};
-use kube::Api;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::chrono;
+use kube::{Api, Resource, ResourceExt};
+use k8s_openapi::NamespaceResourceScope;
+use kube::api::{Patch, PatchParams};
+use kube::core::object::HasStatus;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
 use tokio::task::JoinHandle;
-use crate::types::{HasPostgresAdminConnection, PostgresAdminConnection, PostgresSslMode};
+use crate::types::{ChannelBinding, HasPostgresAdminConnection, PostgresAdminConnection, PostgresAdminConnectionReference, PostgresAdminConnectionSpec, PostgresSslMode, StatusProgress};
 
 pub async fn get_postgres_connection(res: &impl HasPostgresAdminConnection, kubernetes_client: kube::Client) -> anyhow::Result<PostgresConnection> {
+    let ns = res.namespace().expect("Resource should be namespaced");
 
-    let admin_conn = res.get_connection();
-
+    let admin_conn = get_postgres_admin_connection(res.get_connection(), &ns, kubernetes_client).await?;
 
-    let ns = res.namespace().expect("Resource should be namespaced");
-    let ns = admin_conn.namespace.as_ref().unwrap_or(&ns);
+    connect_to_postgres(&admin_conn).await
+}
 
+pub async fn get_postgres_admin_connection(reference: &PostgresAdminConnectionReference, current_namespace: &str, kubernetes_client: kube::Client) -> anyhow::Result<PostgresAdminConnectionSpec> {
+    let ns = reference.namespace.as_deref().unwrap_or(current_namespace);
 
-    let api: Api<PostgresAdminConnection> = Api::namespaced(kubernetes_client, &ns);
+    let api: Api<PostgresAdminConnection> = Api::namespaced(kubernetes_client, ns);
 
-    let admin_conn = api.get_opt(&admin_conn.name).await?;
+    let admin_conn = api.get_opt(&reference.name).await?;
 
-    let admin_conn = if let Some(admin_conn) = admin_conn {
-        admin_conn.spec
+    if let Some(admin_conn) = admin_conn {
+        Ok(admin_conn.spec)
     } else {
         bail!("Could not find postgres admin connection kubernetes object");
-    };
+    }
+}
 
+pub async fn connect_to_postgres(admin_conn: &PostgresAdminConnectionSpec) -> anyhow::Result<PostgresConnection> {
 
     let mut root_store = rustls::RootCertStore::empty();
     root_store.add_trust_anchors(
@@ -96,3 +109,97 @@ impl DerefMut for PostgresConnection {
         &mut self.client
     }
 }
+
+/// Quotes a string for use as a literal in SQL statements that do not accept parameters.
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Creates a libpq connection string for the admin connection, as used by replication and
+/// foreign data wrappers to connect from one server to another.
+pub fn to_connection_string(admin_conn: &PostgresAdminConnectionSpec) -> String {
+    let quote = |value: &str| format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"));
+
+    let mut connection_string = format!(
+        "host={} port={} dbname={} user={} password={} sslmode={}",
+        quote(&admin_conn.host),
+        admin_conn.port,
+        quote(&admin_conn.database),
+        quote(&admin_conn.username),
+        quote(admin_conn.password.get_raw_text()),
+        admin_conn.ssl_mode,
+    );
+
+    if let Some(channel_binding) = admin_conn.channel_binding {
+        connection_string.push_str(match channel_binding {
+            ChannelBinding::Disable => " channel_binding=disable",
+            ChannelBinding::Prefer => " channel_binding=prefer",
+            ChannelBinding::Require => " channel_binding=require",
+        });
+    }
+
+    connection_string
+}
+
+/// Returns the conditions with the condition of the given type replaced. The transition time is
+/// only moved when the status of the condition changes.
+pub fn with_condition(conditions: Option<&Vec<Condition>>, condition_type: &str, status: bool, reason: &str, message: String, observed_generation: Option<i64>) -> Vec<Condition> {
+    let status = if status { "True" } else { "False" }.to_string();
+
+    let mut conditions = conditions.cloned().unwrap_or_default();
+
+    let last_transition_time = conditions.iter()
+        .find(|c| c.type_ == condition_type && c.status == status)
+        .map(|c| c.last_transition_time.clone())
+        .unwrap_or_else(|| Time(chrono::Utc::now()));
+
+    conditions.retain(|c| c.type_ != condition_type);
+    conditions.push(Condition {
+        type_: condition_type.to_string(),
+        status,
+        reason: reason.to_string(),
+        message,
+        observed_generation,
+        last_transition_time,
+    });
+
+    conditions
+}
+
+/// Patches the status when it changed. Every status patch triggers another reconcile, so changes
+/// to only the progress of a status are written at most once a minute, rather than on every
+/// reconcile.
+pub async fn patch_status_if_changed<K>(api: &Api<K>, resource: &K, status: K::Status) -> anyhow::Result<()>
+where
+    K: HasStatus + ResourceExt + Clone + DeserializeOwned + Debug,
+    K::Status: StatusProgress + Serialize,
+{
+    if let Some(current) = resource.status() {
+        let unchanged = current.without_progress() == status.without_progress() && match current.progress_time() {
+            Some(time) => chrono::Utc::now() - time.0 < chrono::Duration::minutes(1),
+            None => current == &status,
+        };
+        if unchanged {
+            return Ok(());
+        }
+    }
+
+    api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(json!({ "status": status }))).await?;
+
+    Ok(())
+}
+
+/// Marks a resource that was ready as not ready after a failed reconcile, so the resources
+/// composed of it, like PostgresTenant, notice.
+pub async fn mark_not_ready<K>(resource: &K, kubernetes_client: kube::Client)
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+    K::DynamicType: Default,
+{
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let api: Api<K> = Api::namespaced(kubernetes_client, &namespace);
+    let status = json!({ "status": { "ready": false } });
+    if let Err(e) = api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await {
+        warn!("Failed to mark {} as not ready: {:?}", resource.name_any(), e);
+    }
+}
@@ -2,4 +2,20 @@ pub mod pg_bouncer;
 pub mod postgres_role;
 mod helpers;
 mod finalizers;
-pub mod postgres_schema;
\ No newline at end of file
+mod schema_template;
+mod pg_bouncer_tls;
+mod pg_bouncer_admin;
+mod pg_bouncer_scaling;
+pub mod postgres_schema;
+pub mod postgres_tenant;
+pub mod postgres_publication;
+pub mod postgres_subscription;
+pub mod postgres_replication_slot;
+pub mod postgres_foreign_server;
+pub mod postgres_user_mapping;
+pub mod postgres_cron_job;
+pub mod postgres_policy;
+pub mod postgres_sql_job;
+pub mod postgres_database_settings;
+pub mod postgres_query_guard;
+pub mod postgres_maintenance;
@@ -1,11 +1,13 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
+use anyhow::{bail, Context};
 use itertools::Itertools;
 use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy, RollingUpdateDeployment};
-use k8s_openapi::api::core::v1::{ConfigMap, ConfigMapVolumeSource, Container, LocalObjectReference, Pod, PodSpec, PodTemplateSpec, Service, ServicePort, ServiceSpec, Volume, VolumeMount};
+use k8s_openapi::api::core::v1::{ConfigMap, ConfigMapProjection, Container, EmptyDirVolumeSource, EnvVar, LocalObjectReference, Pod, PodSpec, PodTemplateSpec, Probe, ProjectedVolumeSource, Secret, SecretProjection, Service, ServicePort, ServiceSpec, TCPSocketAction, TopologySpreadConstraint, Volume, VolumeMount, VolumeProjection};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use k8s_openapi::chrono::{DateTime, Utc};
 use kube::{Api, Resource, ResourceExt};
 use kube::api::{ListParams, Patch, PatchParams};
 use kube_runtime::controller::Action;
@@ -13,14 +15,25 @@ use serde_json::json;
 use sha2::{Digest};
 use crate::{ContextData, Error};
 use crate::helpers::ini_builder;
-use crate::types::{HasPgBouncerReference, PgBouncer, PgBouncerDatabase, PgBouncerDatabaseSpec, PgBouncerSpec, PgBouncerUser, PgBouncerUserSpec};
+use crate::postgres_password::PostgresPassword;
+use crate::reconcilers::helpers::{connect_to_postgres, get_postgres_admin_connection};
+use crate::reconcilers::pg_bouncer_admin::{can_reload, connect_to_admin_console, get_admin_password, ExpectedConfig, reload_pod, shutdown_hooks, OPERATOR_ADMIN_USER};
+use crate::reconcilers::pg_bouncer_scaling::{apply_autoscaler, apply_pod_disruption_budget, connection_replicas, deployment_replicas, per_replica_connections};
+use crate::reconcilers::pg_bouncer_tls::{add_tls_settings, get_tls_files, tls_annotations, tls_volume_projections, TlsFiles};
+use crate::types::{HasPgBouncerReference, PgBouncer, PgBouncerAuthType, PgBouncerDatabase, PgBouncerDatabaseSpec, PgBouncerHbaMethod, PgBouncerHbaRule, PgBouncerPodStatus, PgBouncerReference, PgBouncerSpec, PgBouncerUser, PgBouncerUserSpec, PostgresAdminConnectionReference, PostgresRole};
 
 const PG_BOUNCER_INI_FILE_NAME: &str = "pgbouncer.ini";
 const USERLIST_TXT_FILE_NAME: &str = "userlist.txt";
+const PG_HBA_FILE_NAME: &str = "pg_hba.conf";
 const USERLIST_HASH: &str = "userlisthash";
 const PG_BOUNCER_APP_NAME: &str = "pgbouncer";
+const CONFIG_PROPAGATION_DELAY: Duration = Duration::from_secs(90);
+/// Settings pg_bouncer only reads on startup, any setting starting with `client_tls_` or `server_tls_` is also included.
+const RESTART_SETTINGS: &[&str] = &["listen_addr", "listen_port", "listen_backlog", "unix_socket_dir", "unix_socket_mode", "unix_socket_group", "user", "pidfile", "so_reuseport", "peer_id"];
 
-const PG_BOUNCER_PORT: i32 = 5432;
+const USER_LOOKUP_QUERY: &str = "SELECT uname, phash FROM pgbouncer.user_lookup($1)";
+
+pub const PG_BOUNCER_PORT: i32 = 5432;
 
 pub async fn reconcile_pg_bouncer(resource: Arc<PgBouncer>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
     run_reconciler(resource, context).await.map_err(|e| e.into())
@@ -42,22 +55,95 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
     let databases = Api::<PgBouncerDatabase>::all(context.kubernetes_client.clone())
         .list(&ListParams::default())
         .await?;
-    let databases = databases
+    let databases: Vec<&PgBouncerDatabase> = databases
         .iter()
         .filter(|db| db.is_for(&resource))
-        .map(|db| &db.spec);
+        .collect();
 
     let users = Api::<PgBouncerUser>::all(context.kubernetes_client.clone())
         .list(&ListParams::default())
         .await?;
-    let users = users
+    let mut users: Vec<&PgBouncerUserSpec> = users
         .iter()
         .filter(|u| u.is_for(&resource))
-        .map(|u| &u.spec);
+        .map(|u| &u.spec)
+        .collect();
+
+    let auth_user = if let Some(auth_user_reference) = &resource.spec.pg_bouncer.auth_user {
+        let role_api: Api<PostgresRole> = Api::namespaced(context.kubernetes_client.clone(), auth_user_reference.namespace.as_ref().unwrap_or(&namespace));
+        let Some(role) = role_api.get_opt(&auth_user_reference.name).await? else {
+            error!("Auth user role {} not found", auth_user_reference.name);
+            return Ok(Action::requeue(Duration::from_secs(30)));
+        };
+
+        for db in &databases {
+            if let Some(connection) = &db.spec.connection {
+                let db_namespace = db.namespace().expect("Expected pg_bouncer database to be namespaced");
+                create_user_lookup(connection, &db_namespace, &role.spec.role, context.kubernetes_client.clone()).await
+                    .with_context(|| format!("Failed to create the user lookup function for database {}", db.spec.exposed_database_name))?;
+            }
+        }
+
+        // pg_bouncer logs in as the auth user itself, which requires the plain password
+        let Some(password) = role.spec.password.get_plaintext() else {
+            bail!("The password of auth user role {} must be given in plaintext, as pg_bouncer logs in with it", auth_user_reference.name);
+        };
+
+        Some(PgBouncerUserSpec {
+            username: role.spec.role.clone(),
+            password: Some(PostgresPassword::Plain(password.to_string())),
+            cert: None,
+            pg_bouncer: PgBouncerReference {
+                name: resource_name.clone(),
+                namespace: Some(namespace.clone()),
+            },
+            pool_mode: None,
+            max_user_connections: None,
+            pool_size: None,
+            hba_rules: None,
+        })
+    } else {
+        None
+    };
+    if let Some(auth_user) = &auth_user {
+        if !users.iter().any(|u| u.username == auth_user.username) {
+            users.push(auth_user);
+        }
+    }
+
+
+    let serverside = PatchParams::apply("postgres-topology-operator").force();
+
+    let admin_password = get_admin_password(&resource, &namespace, &serverside, context.kubernetes_client.clone()).await?;
+    let admin_user = PgBouncerUserSpec {
+        username: OPERATOR_ADMIN_USER.to_string(),
+        password: Some(PostgresPassword::Plain(admin_password.clone())),
+        cert: None,
+        pg_bouncer: PgBouncerReference {
+            name: resource_name.clone(),
+            namespace: Some(namespace.clone()),
+        },
+        pool_mode: None,
+        max_user_connections: None,
+        pool_size: None,
+        hba_rules: None,
+    };
+    users.retain(|u| u.username != OPERATOR_ADMIN_USER);
+    users.push(&admin_user);
+
+    let Some(tls_files) = get_tls_files(&resource, &namespace, &serverside, context.kubernetes_client.clone()).await? else {
+        return Ok(Action::requeue(Duration::from_secs(30)));
+    };
 
+    let hba_file = create_hba_file(&resource.spec, databases.iter().map(|db| &db.spec), &users)?;
+    let PgBouncerIni { content: pg_bouncer_ini, restart_settings_hash, settings: ini_settings } = create_pg_bouncer_ini(&resource.spec, databases.iter().map(|db| &db.spec), &users, auth_user.as_ref().map(|u| u.username.as_str()), hba_file.is_some(), &tls_files)?;
+    let (user_list_txt, user_list_hash) = create_user_list(users.iter().copied())?;
 
-    let pg_bouncer_ini = create_pg_bouncer_ini(&resource.spec, databases);
-    let (user_list_txt, user_list_hash) = create_user_list(users);
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(pg_bouncer_ini.as_bytes());
+    hasher.update(hba_file.as_deref().unwrap_or_default().as_bytes());
+    hasher.update(user_list_hash.as_bytes());
+    let config_hash = base16ct::lower::encode_string(&hasher.finalize());
 
 
     let config_map_api: Api<ConfigMap> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
@@ -68,9 +154,6 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
     let config_map_name = format!("{}-config", resource_name);
 
 
-    let serverside = PatchParams::apply("postgres-topology-operator").force();
-
-
     let desired_config_map = ConfigMap {
         metadata: ObjectMeta {
             namespace: Some(namespace.clone()),
@@ -80,10 +163,9 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
             ..Default::default()
         },
         data: Some([
-            (PG_BOUNCER_INI_FILE_NAME.to_string(), pg_bouncer_ini.clone()),
-            (USERLIST_TXT_FILE_NAME.to_string(), user_list_txt.clone()),
-            (USERLIST_HASH.to_string(), user_list_hash.clone()),
-        ].into()),
+            Some((PG_BOUNCER_INI_FILE_NAME.to_string(), pg_bouncer_ini.clone())),
+            hba_file.clone().map(|hba_file| (PG_HBA_FILE_NAME.to_string(), hba_file)),
+        ].into_iter().flatten().collect()),
         ..Default::default()
     };
 
@@ -93,8 +175,8 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
             if existing.get(PG_BOUNCER_INI_FILE_NAME) != Some(&pg_bouncer_ini) {
                 info!("pg_bouncer.ini has changed, updating config map");
                 true
-            } else if existing.get(USERLIST_HASH) != Some(&user_list_hash) {
-                info!("userlist.txt has changed, updating config map");
+            } else if existing.get(PG_HBA_FILE_NAME) != hba_file.as_ref() {
+                info!("pg_hba.conf has changed, updating config map");
                 true
             } else {
                 false
@@ -123,6 +205,51 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
         config_map
     };
 
+    // The user list used to be stored in the config map, where anyone able to read config maps
+    // could read it
+    let config_map = if config_map.data.as_ref().is_some_and(|d| d.contains_key(USERLIST_TXT_FILE_NAME) || d.contains_key(USERLIST_HASH)) {
+        info!("Removing userlist.txt from config map {}", config_map_name);
+        let patch = json!({
+            "data": {
+                USERLIST_TXT_FILE_NAME: null,
+                USERLIST_HASH: null,
+            }
+        });
+        config_map_api.patch(&config_map_name, &PatchParams::default(), &Patch::Merge(&patch)).await?
+    } else {
+        config_map
+    };
+
+
+    let secret_api: Api<Secret> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    let auth_secret_name = format!("{}-auth", resource_name);
+
+    let existing_user_list = secret_api.get_opt(&auth_secret_name).await?
+        .and_then(|s| s.data)
+        .and_then(|mut d| d.remove(USERLIST_TXT_FILE_NAME));
+
+    if existing_user_list.as_ref().map(|d| d.0.as_slice()) != Some(user_list_txt.as_bytes()) {
+        info!("userlist.txt has changed, updating secret {}", auth_secret_name);
+
+        let auth_secret = Secret {
+            metadata: ObjectMeta {
+                namespace: Some(namespace.clone()),
+                name: Some(auth_secret_name.clone()),
+                owner_references: Some(vec![resource.controller_owner_ref(&()).unwrap()]),
+                labels: Some(watcher_labels.clone()),
+                ..Default::default()
+            },
+            string_data: Some([
+                (USERLIST_TXT_FILE_NAME.to_string(), user_list_txt.clone()),
+            ].into()),
+            ..Default::default()
+        };
+
+        secret_api.patch(&auth_secret_name, &serverside, &Patch::Apply(auth_secret)).await?;
+    } else {
+        info!("Auth secret does not need to be updated");
+    }
+
 
     let deployment_name = format!("{}-deployment", resource_name);
     let owner_label = resource.metadata.uid.as_ref().expect("Expected resource to have a UID").to_string();
@@ -132,6 +259,33 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
         ("postgres-topology-operator/pg_bouncer".to_string(), owner_label.clone()),
     ].into();
 
+    // Changes to these annotations roll out new pods, everything else is reloaded
+    let mut pod_annotations = tls_annotations(&tls_files).unwrap_or_default();
+    pod_annotations.insert("postgres-topology-operator/settings_hash".to_string(), restart_settings_hash);
+
+    let shutdown_hooks = shutdown_hooks(&resource, hba_file.is_some(), &tls_files)?;
+    let pod_options = resource.spec.pod_options.clone().unwrap_or_default();
+
+    let mut pod_labels = pod_options.labels.clone().unwrap_or_default();
+    pod_labels.extend(deployment_labels.clone());
+    let mut all_pod_annotations = pod_options.annotations.clone().unwrap_or_default();
+    all_pod_annotations.extend(pod_annotations);
+
+    let env: Vec<EnvVar> = shutdown_hooks.iter().flat_map(|h| h.env.clone())
+        .chain(pod_options.env.iter().flatten().cloned())
+        .collect();
+    let topology_spread_constraints = pod_options.topology_spread_constraints.clone().map(|constraints| {
+        constraints.into_iter()
+            .map(|c| TopologySpreadConstraint {
+                label_selector: c.label_selector.clone().or_else(|| Some(LabelSelector {
+                    match_labels: Some(deployment_labels.clone()),
+                    ..Default::default()
+                })),
+                ..c
+            })
+            .collect()
+    });
+
     let deployment = Deployment {
         metadata: ObjectMeta {
             namespace: Some(namespace.clone()),
@@ -141,6 +295,7 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
             ..Default::default()
         },
         spec: Some(DeploymentSpec {
+            replicas: deployment_replicas(&resource.spec),
             selector: LabelSelector {
                 match_labels: Some(deployment_labels.clone()),
                 ..Default::default()
@@ -154,35 +309,49 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
             }),
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta {
-                    labels: Some(deployment_labels.clone()),
+                    labels: Some(pod_labels),
+                    annotations: Some(all_pod_annotations),
                     ..Default::default()
                 }),
                 spec: Some(PodSpec {
                     volumes: Some(vec![
                         Volume {
                             name: "config".to_string(),
-                            config_map: Some(ConfigMapVolumeSource {
-                                name: config_map.name_any(),
-                                optional: Some(false),
+                            projected: Some(ProjectedVolumeSource {
+                                sources: Some(vec![
+                                    VolumeProjection {
+                                        config_map: Some(ConfigMapProjection {
+                                            name: config_map.name_any(),
+                                            optional: Some(false),
+                                            ..Default::default()
+                                        }),
+                                        ..Default::default()
+                                    },
+                                    VolumeProjection {
+                                        secret: Some(SecretProjection {
+                                            name: auth_secret_name.clone(),
+                                            optional: Some(false),
+                                            ..Default::default()
+                                        }),
+                                        ..Default::default()
+                                    },
+                                ].into_iter().chain(tls_volume_projections(&tls_files)).collect()),
                                 ..Default::default()
                             }),
                             ..Default::default()
-                        }
+                        },
+                        Volume {
+                            name: "tmp".to_string(),
+                            empty_dir: Some(EmptyDirVolumeSource::default()),
+                            ..Default::default()
+                        },
                     ]),
-                    image_pull_secrets: resource
-                        .spec
-                        .pod_options
-                        .as_ref()
-                        .and_then(|p| p.image_pull_secret.clone())
+                    image_pull_secrets: pod_options.image_pull_secret.clone()
                         .map(|s| vec![LocalObjectReference { name: s }]),
                     containers: vec![
                         Container {
                             name: "pg-bouncer".to_string(),
-                            image: resource
-                                .spec
-                                .pod_options
-                                .as_ref()
-                                .and_then(|p| p.image.clone())
+                            image: pod_options.image.clone()
                                 .or_else(|| {
                                     Some("ghcr.io/digizuite/digi-pg-bouncer:task-DEPLOY-22".to_string())
                                 }),
@@ -193,13 +362,36 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
                                     name: "config".to_string(),
                                     read_only: Some(true),
                                     ..Default::default()
-                                }
+                                },
+                                VolumeMount {
+                                    mount_path: "/tmp".to_string(),
+                                    name: "tmp".to_string(),
+                                    ..Default::default()
+                                },
                             ]),
-                            resources: resource.spec.pod_options.as_ref().and_then(|o| o.resources.clone()),
+                            resources: pod_options.resources.clone(),
+                            lifecycle: shutdown_hooks.as_ref().map(|h| h.lifecycle.clone()),
+                            readiness_probe: shutdown_hooks.as_ref().map(|h| h.readiness_probe.clone()).or_else(|| Some(Probe {
+                                tcp_socket: Some(TCPSocketAction {
+                                    port: IntOrString::Int(PG_BOUNCER_PORT),
+                                    ..Default::default()
+                                }),
+                                period_seconds: Some(5),
+                                ..Default::default()
+                            })),
+                            env: Some(env).filter(|e| !e.is_empty()),
+                            security_context: pod_options.container_security_context.clone(),
                             ..Default::default()
                         }
-                    ],
-                    node_selector: resource.spec.pod_options.as_ref().and_then(|o| o.node_selector.clone()),
+                    ].into_iter().chain(pod_options.sidecars.iter().flatten().cloned()).collect(),
+                    node_selector: pod_options.node_selector.clone(),
+                    tolerations: pod_options.tolerations.clone(),
+                    affinity: pod_options.affinity.clone(),
+                    topology_spread_constraints,
+                    priority_class_name: pod_options.priority_class_name.clone(),
+                    service_account_name: pod_options.service_account_name.clone(),
+                    security_context: pod_options.security_context.clone(),
+                    termination_grace_period_seconds: shutdown_hooks.as_ref().map(|h| h.termination_grace_period_seconds),
                     ..Default::default()
                 }),
             },
@@ -212,24 +404,118 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
 
     info!("Deployment created");
 
+    apply_autoscaler(&resource, &namespace, &deployment_name, &watcher_labels, &serverside, context.kubernetes_client.clone()).await?;
+    apply_pod_disruption_budget(&resource, &namespace, &deployment_name, &watcher_labels, &deployment_labels, &serverside, context.kubernetes_client.clone()).await?;
+
+    let status = resource.status.clone().unwrap_or_default();
+    let now = Utc::now();
+    let config_changed = status.config_hash.as_ref() != Some(&config_hash);
+    let config_changed_time = status.config_changed_time.as_deref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .filter(|_| !config_changed)
+        .unwrap_or(now);
+    // The kubelet only updates the mounted config files after a while
+    let propagated = (now - config_changed_time).to_std().unwrap_or_default() >= CONFIG_PROPAGATION_DELAY;
+
+    let expected_config = ExpectedConfig::new(
+        ini_settings,
+        &databases.iter().map(|db| &db.spec).collect::<Vec<_>>(),
+        &users,
+        connection_replicas(&resource.spec),
+    );
+    let reloadable = can_reload(&resource, hba_file.is_some(), &tls_files);
+
     let pod_api = Api::<Pod>::namespaced(context.kubernetes_client.clone(), &namespace);
     let pg_bouncer_pods = pod_api
         .list(&ListParams::default().labels(&format!("postgres-topology-operator/pg_bouncer={owner_label}")))
         .await?;
 
-    for pod in pg_bouncer_pods.items.iter() {
-        let hash_annotation = json!({
-            "metadata": {
-                "annotations": {
-                    "postgres-topology-operator/pg_bouncer_config_hash": user_list_hash.clone(),
-                }
+    let mut pod_statuses = vec![];
+    let mut failed_reload = false;
+    if reloadable {
+        let ready_pods = pg_bouncer_pods.items.iter()
+            .filter(|p| p.metadata.deletion_timestamp.is_none())
+            .filter(|p| p.status.as_ref().and_then(|s| s.conditions.as_ref()).is_some_and(|c| c.iter().any(|c| c.type_ == "Ready" && c.status == "True")));
+        for pod in ready_pods {
+            let Some(pod_ip) = pod.status.as_ref().and_then(|s| s.pod_ip.as_ref()) else {
+                continue;
+            };
+
+            let previous = status.pods.iter().flatten().find(|p| p.name == pod.name_any());
+            if let Some(previous) = previous.filter(|p| p.loaded_config_hash.as_ref() == Some(&config_hash)) {
+                pod_statuses.push(previous.clone());
+                continue;
             }
-        });
 
-        let patch = Patch::Merge(&hash_annotation);
-        pod_api.patch(&pod.name_any(), &PatchParams::default(), &patch).await?;
+            let mut pod_status = PgBouncerPodStatus {
+                name: pod.name_any(),
+                loaded_config_hash: previous.and_then(|p| p.loaded_config_hash.clone()),
+                reloaded_config_hash: previous.and_then(|p| p.reloaded_config_hash.clone()),
+                ..Default::default()
+            };
+            if !propagated {
+                pod_statuses.push(pod_status);
+                continue;
+            }
+
+            // RELOAD is sent once for each config, after that the pod is only verified
+            let reload = pod_status.reloaded_config_hash.as_ref() != Some(&config_hash);
+            let result = match connect_to_admin_console(pod_ip, &resource, &admin_password, &tls_files).await {
+                Ok(pg_connection) => {
+                    if reload {
+                        pod_status.reloaded_config_hash = Some(config_hash.clone());
+                    }
+                    let result = reload_pod(&pg_connection, reload, &expected_config).await;
+                    failed_reload |= reload && !result.as_ref().is_ok_and(|d| d.is_empty());
+                    result
+                },
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(differences) => {
+                    pod_status.up_to_date = differences.is_empty();
+                    if pod_status.up_to_date {
+                        pod_status.loaded_config_hash = Some(config_hash.clone());
+                    }
+                    pod_status.differences = Some(differences).filter(|d| !d.is_empty());
+                },
+                Err(e) => {
+                    warn!("Failed to reload pg_bouncer pod {}: {:?}", pod.name_any(), e);
+                    pod_status.error = Some(format!("{:#}", e));
+                },
+            }
+            pod_statuses.push(pod_status);
+        }
     }
 
+    // Pods are created with the current config, so there is nothing to restart for the first config
+    let must_restart = if status.config_hash.is_none() || status.restarted_config_hash.as_ref() == Some(&config_hash) {
+        false
+    } else if reloadable {
+        failed_reload
+    } else {
+        config_changed
+    };
+
+    let mut restarted_config_hash = status.restarted_config_hash.clone();
+    if must_restart {
+        info!("Config of pg_bouncer {} could not be reloaded, restarting the pods", resource_name);
+        let restart = json!({
+            "spec": {
+                "template": {
+                    "metadata": {
+                        "annotations": {
+                            "postgres-topology-operator/restartedAt": now.to_rfc3339(),
+                        }
+                    }
+                }
+            }
+        });
+        deployments_api.patch(&deployment_name, &PatchParams::default(), &Patch::Merge(&restart)).await?;
+        restarted_config_hash = Some(config_hash.clone());
+    }
 
     let service = Service {
         metadata: ObjectMeta {
@@ -263,7 +549,11 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
     {
         let status = json!({
             "status": {
-                "lastUserConfigHash": user_list_hash.clone()
+                "lastUserConfigHash": user_list_hash.clone(),
+                "configHash": config_hash,
+                "configChangedTime": config_changed_time.to_rfc3339(),
+                "restartedConfigHash": restarted_config_hash,
+                "pods": pod_statuses,
             }
         });
 
@@ -273,54 +563,299 @@ async fn run_reconciler(resource: Arc<PgBouncer>, context: Arc<ContextData>) ->
 
 
     info!("Finished pg bouncer reconcilation");
-    Ok(Action::requeue(Duration::from_secs(60)))
+    let reload_pending = !propagated || pod_statuses.iter().any(|p| p.reloaded_config_hash.as_ref() != Some(&config_hash));
+    if reloadable && reload_pending {
+        Ok(Action::requeue(Duration::from_secs(15)))
+    } else {
+        Ok(Action::requeue(Duration::from_secs(60)))
+    }
 }
 
-fn create_pg_bouncer_ini<'a>(spec: &PgBouncerSpec, databases: impl Iterator<Item=&'a PgBouncerDatabaseSpec>) -> String {
+struct PgBouncerIni {
+    content: String,
+    /// Hash of the settings that are only read on startup.
+    restart_settings_hash: String,
+    /// The `[pgbouncer]` settings set by the operator. `extraSettings` are left out, as
+    /// pg_bouncer may show them differently than they are configured.
+    settings: Vec<(String, String)>,
+}
+
+fn create_pg_bouncer_ini<'a>(spec: &PgBouncerSpec, databases: impl Iterator<Item=&'a PgBouncerDatabaseSpec>, users: &[&PgBouncerUserSpec], auth_user: Option<&str>, use_hba: bool, tls_files: &TlsFiles) -> anyhow::Result<PgBouncerIni> {
     let mut builder = ini_builder::new();
 
     builder.add_section("pgbouncer");
 
     let settings = &spec.pg_bouncer;
+    let databases: Vec<&PgBouncerDatabaseSpec> = databases.collect();
+    let mut hba_rules = spec.hba_rules.iter().flatten()
+        .chain(users.iter().flat_map(|u| u.hba_rules.iter().flatten()))
+        .chain(databases.iter().flat_map(|db| db.hba_rules.iter().flatten()));
 
     builder.add_setting("pool_mode", &settings.pool_mode);
     builder.add_setting("listen_port", PG_BOUNCER_PORT);
     builder.add_setting("listen_addr", "0.0.0.0");
-    builder.add_setting("auth_type", &settings.auth_type);
-    if let Some(admin_users) = &settings.admin_users {
-        builder.add_comma_separated("admin_users", admin_users);
+    if use_hba {
+        builder.add_setting("auth_type", PgBouncerAuthType::Hba);
+        builder.add_setting("auth_hba_file", format!("/etc/pgbouncer/{}", PG_HBA_FILE_NAME));
+    } else if settings.auth_type == PgBouncerAuthType::Hba {
+        bail!("The hba auth type requires hba rules");
+    } else {
+        builder.add_setting("auth_type", &settings.auth_type);
     }
+    let admin_users: Vec<&str> = settings.admin_users.iter().flatten().map(|u| u.as_str()).chain([OPERATOR_ADMIN_USER]).collect();
+    builder.add_comma_separated("admin_users", &admin_users);
     if let Some(ignore_startup_parameters) = &settings.ignore_startup_parameters {
         builder.add_comma_separated("ignore_startup_parameters", ignore_startup_parameters);
     }
     builder.add_setting("server_tls_sslmode", &settings.server_tls_ssl_mode);
     builder.add_setting("client_tls_sslmode", &settings.client_tls_ssl_mode);
+    let uses_cert_auth = if use_hba {
+        hba_rules.any(|r| r.method == PgBouncerHbaMethod::Cert)
+    } else {
+        settings.auth_type == PgBouncerAuthType::Cert
+    };
+    add_tls_settings(&mut builder, settings, tls_files, uses_cert_auth)?;
     builder.add_setting("max_client_conn", settings.max_client_conn);
-    builder.add_setting("max_db_connections", settings.max_db_connections);
+    let replicas = connection_replicas(spec);
+    builder.add_setting("max_db_connections", per_replica_connections(settings.max_db_connections, replicas));
+    builder.add_optional_setting("max_user_connections", settings.max_user_connections);
     builder.add_setting("auth_file", format!("/etc/pgbouncer/{}", USERLIST_TXT_FILE_NAME));
+    if let Some(auth_user) = auth_user {
+        builder.add_setting("auth_user", auth_user);
+        builder.add_setting("auth_query", USER_LOOKUP_QUERY);
+    }
+
+    builder.add_optional_setting("default_pool_size", settings.default_pool_size);
+    builder.add_optional_setting("min_pool_size", settings.min_pool_size);
+    builder.add_optional_setting("reserve_pool_size", settings.reserve_pool_size);
+    builder.add_optional_setting("reserve_pool_timeout", settings.reserve_pool_timeout);
+    builder.add_optional_setting("max_prepared_statements", settings.max_prepared_statements);
+
+    builder.add_optional_setting("server_reset_query", settings.server_reset_query.as_ref());
+    builder.add_optional_bool("server_reset_query_always", settings.server_reset_query_always);
+    builder.add_optional_setting("server_check_query", settings.server_check_query.as_ref());
+    builder.add_optional_setting("server_check_delay", settings.server_check_delay);
+    builder.add_optional_bool("server_round_robin", settings.server_round_robin);
+
+    builder.add_optional_setting("server_lifetime", settings.server_lifetime);
+    builder.add_optional_setting("server_idle_timeout", settings.server_idle_timeout);
+    builder.add_optional_setting("server_connect_timeout", settings.server_connect_timeout);
+    builder.add_optional_setting("server_login_retry", settings.server_login_retry);
+    builder.add_optional_setting("query_timeout", settings.query_timeout);
+    builder.add_optional_setting("query_wait_timeout", settings.query_wait_timeout);
+    builder.add_optional_setting("client_idle_timeout", settings.client_idle_timeout);
+    builder.add_optional_setting("client_login_timeout", settings.client_login_timeout);
+    builder.add_optional_setting("idle_transaction_timeout", settings.idle_transaction_timeout);
+
+    if let Some(stats_users) = &settings.stats_users {
+        builder.add_comma_separated("stats_users", stats_users);
+    }
+    builder.add_optional_setting("stats_period", settings.stats_period);
+    builder.add_optional_bool("log_connections", settings.log_connections);
+    builder.add_optional_bool("log_disconnections", settings.log_disconnections);
+    builder.add_optional_bool("log_pooler_errors", settings.log_pooler_errors);
+    builder.add_optional_bool("log_stats", settings.log_stats);
+    builder.add_optional_setting("verbose", settings.verbose);
+    builder.add_optional_bool("application_name_add_host", settings.application_name_add_host);
+    builder.add_optional_bool("tcp_keepalive", settings.tcp_keepalive);
+
+    let operator_settings = builder.settings("pgbouncer")
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    for (key, value) in settings.extra_settings.iter().flatten() {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            bail!("Invalid pg_bouncer setting name {:?}", key);
+        }
+        if builder.has_setting(key) {
+            bail!("The pg_bouncer setting {} is already configured, and cannot be set through extraSettings", key);
+        }
+        if value.contains(['\n', '\r']) {
+            bail!("The value of the pg_bouncer setting {} cannot span multiple lines", key);
+        }
+        builder.add_setting(key, value);
+    }
 
 
     builder.add_section("databases");
     for db in databases {
-        let key = &db.exposed_database_name;
-        let mut value = String::new();
-        value.push_str(&format!("host={} ", db.host));
-        if let Some(port) = &db.port {
-            value.push_str(&format!("port={} ", port));
+        builder.add_setting(&db.exposed_database_name, create_database_line(db, replicas));
+    }
+
+    add_users_section(&mut builder, users);
+
+    let mut hasher = sha2::Sha256::new();
+    for (key, value) in builder.settings("pgbouncer") {
+        if RESTART_SETTINGS.contains(&key) || key.starts_with("client_tls_") || key.starts_with("server_tls_") {
+            hasher.update(format!("{}={}\n", key, value).as_bytes());
+        }
+    }
+    let restart_settings_hash = base16ct::lower::encode_string(&hasher.finalize());
+
+    Ok(PgBouncerIni {
+        content: builder.build(),
+        restart_settings_hash,
+        settings: operator_settings,
+    })
+}
+
+/// Creates the function pg_bouncer looks up users with. It is owned by the admin user, which has
+/// to be a superuser to read `pg_shadow`.
+async fn create_user_lookup(connection: &PostgresAdminConnectionReference, namespace: &str, auth_user: &str, kubernetes_client: kube::Client) -> anyhow::Result<()> {
+    let admin_connection = get_postgres_admin_connection(connection, namespace, kubernetes_client).await?;
+    let pg_connection = connect_to_postgres(&admin_connection).await?;
+
+    let exists = pg_connection.query_opt("
+        SELECT 1
+        FROM pg_proc p
+            JOIN pg_namespace n ON n.oid = p.pronamespace
+        WHERE n.nspname = 'pgbouncer' AND p.proname = 'user_lookup'", &[]).await?.is_some();
+
+    if !exists {
+        info!("Creating user lookup function in database {}", pg_connection.database);
+        pg_connection.batch_execute("
+            CREATE SCHEMA IF NOT EXISTS pgbouncer;
+            CREATE FUNCTION pgbouncer.user_lookup(i_username text, OUT uname text, OUT phash text) RETURNS record AS $$
+            BEGIN
+                SELECT usename, passwd FROM pg_catalog.pg_shadow WHERE usename = i_username INTO uname, phash;
+                RETURN;
+            END;
+            $$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = pg_catalog;
+            REVOKE ALL ON FUNCTION pgbouncer.user_lookup(text) FROM PUBLIC;").await?;
+    }
+
+    pg_connection.batch_execute(&format!("
+        GRANT USAGE ON SCHEMA pgbouncer TO {auth_user};
+        GRANT EXECUTE ON FUNCTION pgbouncer.user_lookup(text) TO {auth_user};")).await?;
+
+    Ok(())
+}
+
+/// Rules of users and databases are more specific, so they come before the rules of the bouncer.
+fn create_hba_file<'a>(spec: &PgBouncerSpec, databases: impl Iterator<Item=&'a PgBouncerDatabaseSpec>, users: &[&PgBouncerUserSpec]) -> anyhow::Result<Option<String>> {
+    let mut lines = vec![];
+
+    for user in users.iter().sorted_by_key(|u| &u.username) {
+        for rule in user.hba_rules.iter().flatten() {
+            lines.push(create_hba_line(rule, rule.databases.as_deref(), Some(std::slice::from_ref(&user.username)))?);
         }
-        if let Some(user) = &db.user {
-            value.push_str(&format!("user={} ", user));
+    }
+
+    for db in databases.sorted_by_key(|db| &db.exposed_database_name) {
+        for rule in db.hba_rules.iter().flatten() {
+            lines.push(create_hba_line(rule, Some(std::slice::from_ref(&db.exposed_database_name)), rule.users.as_deref())?);
         }
-        if let Some(name) = &db.internal_database_name {
-            value.push_str(&format!("dbname={} ", name));
+    }
+
+    for rule in spec.hba_rules.iter().flatten() {
+        lines.push(create_hba_line(rule, rule.databases.as_deref(), rule.users.as_deref())?);
+    }
+
+    if lines.is_empty() {
+        Ok(None)
+    } else {
+        // The operator has to be able to reach the admin console to reload the config
+        for address in ["0.0.0.0/0", "::/0"] {
+            lines.insert(0, format!("host pgbouncer {} {} scram-sha-256\n", OPERATOR_ADMIN_USER, address));
         }
-        builder.add_setting(key, value);
+        Ok(Some(lines.join("")))
+    }
+}
+
+fn create_hba_line(rule: &PgBouncerHbaRule, databases: Option<&[String]>, users: Option<&[String]>) -> anyhow::Result<String> {
+    let list = |values: Option<&[String]>| values
+        .filter(|v| !v.is_empty())
+        .map(|v| v.join(","))
+        .unwrap_or_else(|| "all".to_string());
+
+    let databases = list(databases);
+    let users = list(users);
+
+    for value in [&databases, &users, &rule.address] {
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            bail!("Invalid hba rule value {:?}", value);
+        }
+    }
+
+    Ok(format!("{} {} {} {} {}\n", rule.connection_type.clone().unwrap_or_default(), databases, users, rule.address, rule.method))
+}
+
+fn create_database_line(db: &PgBouncerDatabaseSpec, replicas: u32) -> String {
+    let mut value = String::new();
+    value.push_str(&format!("host={} ", db.host));
+    if let Some(port) = &db.port {
+        value.push_str(&format!("port={} ", port));
+    }
+    if let Some(user) = &db.user {
+        value.push_str(&format!("user={} ", user));
+    }
+    if let Some(name) = &db.internal_database_name {
+        value.push_str(&format!("dbname={} ", name));
+    }
+    if let Some(auth_user) = &db.auth_user {
+        value.push_str(&format!("auth_user={} ", auth_user));
+    }
+    if let Some(pool_size) = db.pool_size {
+        value.push_str(&format!("pool_size={} ", pool_size));
+    }
+    if let Some(min_pool_size) = db.min_pool_size {
+        value.push_str(&format!("min_pool_size={} ", min_pool_size));
     }
+    if let Some(reserve_pool) = db.reserve_pool {
+        value.push_str(&format!("reserve_pool={} ", reserve_pool));
+    }
+    if let Some(pool_mode) = &db.pool_mode {
+        value.push_str(&format!("pool_mode={} ", pool_mode));
+    }
+    if let Some(max_db_connections) = db.max_db_connections {
+        value.push_str(&format!("max_db_connections={} ", per_replica_connections(max_db_connections, replicas)));
+    }
+    // Values with spaces have to be quoted, with quotes inside doubled
+    let quoted_settings = [
+        ("connect_query", &db.connect_query),
+        ("client_encoding", &db.client_encoding),
+        ("datestyle", &db.datestyle),
+        ("timezone", &db.timezone),
+    ];
+    for (key, setting) in quoted_settings {
+        if let Some(setting) = setting {
+            value.push_str(&format!("{}='{}' ", key, setting.replace('\'', "''")));
+        }
+    }
+    value
+}
 
-    builder.build()
+fn create_user_line(user: &PgBouncerUserSpec) -> String {
+    let mut value = String::new();
+    if let Some(pool_mode) = &user.pool_mode {
+        value.push_str(&format!("pool_mode={} ", pool_mode));
+    }
+    if let Some(max_user_connections) = user.max_user_connections {
+        value.push_str(&format!("max_user_connections={} ", max_user_connections));
+    }
+    if let Some(pool_size) = user.pool_size {
+        value.push_str(&format!("pool_size={} ", pool_size));
+    }
+    value
 }
 
-fn create_user_list<'a>(users: impl Iterator<Item=&'a PgBouncerUserSpec>) -> (String, String) {
+/// Only users with settings of their own are added, and the section is left out when there are
+/// none.
+fn add_users_section(builder: &mut ini_builder::IniBuilder, users: &[&PgBouncerUserSpec]) {
+    let users_with_settings = users.iter()
+        .map(|u| (&u.username, create_user_line(u)))
+        .filter(|(_, line)| !line.is_empty())
+        .sorted()
+        .collect::<Vec<_>>();
+    if !users_with_settings.is_empty() {
+        builder.add_section("users");
+        for (username, line) in users_with_settings {
+            builder.add_setting(username, line);
+        }
+    }
+}
+
+fn create_user_list<'a>(users: impl Iterator<Item=&'a PgBouncerUserSpec>) -> anyhow::Result<(String, String)> {
     let users = users.sorted_by_key(|u| &u.username);
 
     let mut hasher = sha2::Sha256::new();
@@ -330,11 +865,167 @@ fn create_user_list<'a>(users: impl Iterator<Item=&'a PgBouncerUserSpec>) -> (St
     for user in users {
         debug!("Adding user {}", user.username);
         hasher.update(user.username.as_bytes());
-        hasher.update(user.password.get_raw_text().as_bytes());
-        let password_text = user.password.get_password_text(&user.username);
+        let password_text = match &user.password {
+            _ if user.cert == Some(true) => String::new(),
+            Some(password) => {
+                hasher.update(password.get_raw_text().as_bytes());
+                password.get_password_text(&user.username)
+            },
+            None => bail!("User {} needs a password, unless it authenticates with a certificate", user.username),
+        };
         s.push_str(&format!("\"{}\" \"{}\"\n", user.username, password_text))
     }
 
     let hash = &hasher.finalize()[..];
-    (s, base16ct::lower::encode_string(hash))
-}
\ No newline at end of file
+    Ok((s, base16ct::lower::encode_string(hash)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::postgres_password::PostgresPassword;
+    use crate::reconcilers::pg_bouncer_tls::TlsFiles;
+    use crate::types::{PgBouncerDatabaseSpec, PgBouncerHbaConnectionType, PgBouncerHbaMethod, PgBouncerHbaRule, PgBouncerUserSpec, PgBouncerPoolMode, PgBouncerReference, PgBouncerServiceSettings, PgBouncerSettings, PgBouncerSpec};
+
+    #[test]
+    fn test_extra_settings() {
+        let mut spec = PgBouncerSpec {
+            pg_bouncer: PgBouncerSettings {
+                default_pool_size: Some(20),
+                log_connections: Some(false),
+                extra_settings: Some(BTreeMap::from([("so_reuseport".to_string(), "1".to_string())])),
+                ..Default::default()
+            },
+            pod_options: None,
+            service: PgBouncerServiceSettings::default(),
+            hba_rules: None,
+            tls: None,
+            shutdown: None,
+            replicas: None,
+            autoscaling: None,
+            pod_disruption_budget: None,
+        };
+
+        let super::PgBouncerIni { content: ini, restart_settings_hash: restart_hash, .. } = super::create_pg_bouncer_ini(&spec, std::iter::empty(), &[], None, false, &TlsFiles::default()).unwrap();
+        assert!(ini.contains("default_pool_size = 20\n"));
+        assert!(ini.contains("log_connections = 0\n"));
+        assert!(ini.contains("so_reuseport = 1\n"));
+
+        // Only settings that cannot be reloaded change the restart hash
+        spec.pg_bouncer.default_pool_size = Some(30);
+        let reloadable_hash = super::create_pg_bouncer_ini(&spec, std::iter::empty(), &[], None, false, &TlsFiles::default()).unwrap().restart_settings_hash;
+        assert_eq!(restart_hash, reloadable_hash);
+        spec.pg_bouncer.extra_settings = Some(BTreeMap::from([("so_reuseport".to_string(), "0".to_string())]));
+        let restart_setting_hash = super::create_pg_bouncer_ini(&spec, std::iter::empty(), &[], None, false, &TlsFiles::default()).unwrap().restart_settings_hash;
+        assert_ne!(restart_hash, restart_setting_hash);
+
+        spec.pg_bouncer.extra_settings = Some(BTreeMap::from([("default_pool_size".to_string(), "5".to_string())]));
+        assert!(super::create_pg_bouncer_ini(&spec, std::iter::empty(), &[], None, false, &TlsFiles::default()).is_err());
+
+        spec.pg_bouncer.extra_settings = Some(BTreeMap::from([("auth_file".to_string(), "/tmp/users".to_string())]));
+        assert!(super::create_pg_bouncer_ini(&spec, std::iter::empty(), &[], None, false, &TlsFiles::default()).is_err());
+
+        spec.pg_bouncer.extra_settings = Some(BTreeMap::from([("so_reuseport".to_string(), "1\n[databases]".to_string())]));
+        assert!(super::create_pg_bouncer_ini(&spec, std::iter::empty(), &[], None, false, &TlsFiles::default()).is_err());
+    }
+
+    #[test]
+    fn test_database_line() {
+        let db = PgBouncerDatabaseSpec {
+            exposed_database_name: "app".to_string(),
+            internal_database_name: Some("app_internal".to_string()),
+            host: "postgres".to_string(),
+            port: None,
+            user: None,
+            pg_bouncer: PgBouncerReference::default(),
+            pool_size: Some(5),
+            min_pool_size: None,
+            reserve_pool: None,
+            pool_mode: Some(PgBouncerPoolMode::Session),
+            max_db_connections: Some(10),
+            connect_query: Some("SET application_name = 'app'".to_string()),
+            client_encoding: None,
+            datestyle: None,
+            timezone: Some("UTC".to_string()),
+            auth_user: None,
+            connection: None,
+            hba_rules: None,
+        };
+
+        assert_eq!(
+            super::create_database_line(&db, 1),
+            "host=postgres dbname=app_internal pool_size=5 pool_mode=session max_db_connections=10 connect_query='SET application_name = ''app''' timezone='UTC' "
+        );
+        // The connections are divided between the replicas
+        assert!(super::create_database_line(&db, 3).contains(" max_db_connections=3 "));
+    }
+
+    #[test]
+    fn test_users_section() {
+        let user = |username: &str, pool_mode: Option<PgBouncerPoolMode>, max_user_connections: Option<u32>, pool_size: Option<u32>| PgBouncerUserSpec {
+            username: username.to_string(),
+            password: None,
+            cert: None,
+            pg_bouncer: PgBouncerReference::default(),
+            pool_mode,
+            max_user_connections,
+            pool_size,
+            hba_rules: None,
+        };
+        let reporting = user("reporting", Some(PgBouncerPoolMode::Transaction), Some(20), Some(5));
+        let app = user("app", None, None, Some(10));
+        let plain = user("plain", None, None, None);
+
+        assert_eq!(super::create_user_line(&reporting), "pool_mode=transaction max_user_connections=20 pool_size=5 ");
+
+        let mut builder = crate::helpers::ini_builder::new();
+        super::add_users_section(&mut builder, &[&reporting, &plain, &app]);
+        assert_eq!(builder.build(), "[users]\napp = pool_size=10 \nreporting = pool_mode=transaction max_user_connections=20 pool_size=5 \n");
+
+        // Without any user settings the section is left out
+        let mut builder = crate::helpers::ini_builder::new();
+        super::add_users_section(&mut builder, &[&plain]);
+        assert_eq!(builder.build(), "");
+    }
+
+    #[test]
+    fn test_hba_file() {
+        let rule = |address: &str, method: PgBouncerHbaMethod| PgBouncerHbaRule {
+            connection_type: None,
+            databases: None,
+            users: None,
+            address: address.to_string(),
+            method,
+        };
+
+        let spec = PgBouncerSpec {
+            pg_bouncer: PgBouncerSettings::default(),
+            pod_options: None,
+            service: PgBouncerServiceSettings::default(),
+            hba_rules: Some(vec![rule("0.0.0.0/0", PgBouncerHbaMethod::Reject)]),
+            tls: None,
+            shutdown: None,
+            replicas: None,
+            autoscaling: None,
+            pod_disruption_budget: None,
+        };
+        let user = PgBouncerUserSpec {
+            username: "app".to_string(),
+            password: Some(PostgresPassword::Plain("password".to_string())),
+            cert: None,
+            pg_bouncer: PgBouncerReference::default(),
+            pool_mode: None,
+            max_user_connections: None,
+            pool_size: None,
+            hba_rules: Some(vec![PgBouncerHbaRule {
+                connection_type: Some(PgBouncerHbaConnectionType::HostSsl),
+                databases: Some(vec!["app".to_string(), "reporting".to_string()]),
+                ..rule("10.0.0.0/8", PgBouncerHbaMethod::ScramSha256)
+            }]),
+        };
+
+        let hba_file = super::create_hba_file(&spec, std::iter::empty(), &[&user]).unwrap();
+
+        assert_eq!(hba_file.as_deref(), Some("host pgbouncer postgres_topology_operator ::/0 scram-sha-256\nhost pgbouncer postgres_topology_operator 0.0.0.0/0 scram-sha-256\nhostssl app,reporting app 10.0.0.0/8 scram-sha-256\nhost all all 0.0.0.0/0 reject\n"));
+    }
+}
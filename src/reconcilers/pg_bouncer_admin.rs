@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+use anyhow::bail;
+use k8s_openapi::api::core::v1::{EnvVar, EnvVarSource, ExecAction, Lifecycle, LifecycleHandler, Probe, Secret, SecretKeySelector};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{Api, Resource, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use rand::distributions::{Alphanumeric, DistString};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use tokio_postgres::{SimpleQueryMessage, SimpleQueryRow};
+use tokio_postgres::config::SslMode;
+use tokio_postgres_rustls::MakeRustlsConnect;
+use crate::reconcilers::pg_bouncer::PG_BOUNCER_PORT;
+use crate::reconcilers::pg_bouncer_scaling::per_replica_connections;
+use crate::reconcilers::pg_bouncer_tls::{admin_certificate_paths, TlsFiles};
+use crate::types::{PgBouncer, PgBouncerAuthType, PgBouncerDatabaseSpec, PgBouncerShutdownMode, PgBouncerUserSpec, PostgresSslMode};
+
+/// The admin user the operator connects to the admin console of pg_bouncer with.
+pub const OPERATOR_ADMIN_USER: &str = "postgres_topology_operator";
+
+const ADMIN_PASSWORD_KEY: &str = "password";
+const DEFAULT_POSTGRES_PORT: u16 = 5432;
+/// Created by the preStop hook, the readiness probe fails while it exists.
+const DRAIN_FILE: &str = "/tmp/pgbouncer-drain";
+
+pub struct ShutdownHooks {
+    pub lifecycle: Lifecycle,
+    pub readiness_probe: Probe,
+    pub env: Vec<EnvVar>,
+    pub termination_grace_period_seconds: i64,
+}
+
+fn admin_secret_name(resource: &PgBouncer) -> String {
+    format!("{}-admin", resource.name_any())
+}
+
+/// Returns the password of the operator admin user, generating it the first time.
+pub async fn get_admin_password(resource: &PgBouncer, namespace: &str, serverside: &PatchParams, kubernetes_client: kube::Client) -> anyhow::Result<String> {
+    let secret_api: Api<Secret> = Api::namespaced(kubernetes_client, namespace);
+    let secret_name = admin_secret_name(resource);
+
+    let existing = secret_api.get_opt(&secret_name).await?
+        .and_then(|s| s.data)
+        .and_then(|mut d| d.remove(ADMIN_PASSWORD_KEY));
+    if let Some(password) = existing {
+        return Ok(String::from_utf8(password.0)?);
+    }
+
+    info!("Generating admin password for pg_bouncer {}", resource.name_any());
+    let password = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+
+    let secret = Secret {
+        metadata: ObjectMeta {
+            namespace: Some(namespace.to_string()),
+            name: Some(secret_name.clone()),
+            owner_references: Some(vec![resource.controller_owner_ref(&()).unwrap()]),
+            labels: Some(BTreeMap::from([("controller-watcher".to_string(), "postgres-topology-operator".to_string())])),
+            ..Default::default()
+        },
+        string_data: Some(BTreeMap::from([
+            ("username".to_string(), OPERATOR_ADMIN_USER.to_string()),
+            (ADMIN_PASSWORD_KEY.to_string(), password.clone()),
+        ])),
+        ..Default::default()
+    };
+    secret_api.patch(&secret_name, serverside, &Patch::Apply(secret)).await?;
+
+    Ok(password)
+}
+
+/// The preStop hook marks the pod as draining, waits for the service to stop routing to it, and
+/// shuts pg_bouncer down through the admin console.
+pub fn shutdown_hooks(resource: &PgBouncer, uses_hba: bool, tls_files: &TlsFiles) -> anyhow::Result<Option<ShutdownHooks>> {
+    let Some(shutdown) = &resource.spec.shutdown else {
+        return Ok(None);
+    };
+    if !can_reload(resource, uses_hba, tls_files) {
+        bail!("Draining requires a client certificate for the admin console, which is only issued when tls.certificate is used");
+    }
+    let termination_grace_period_seconds = shutdown.termination_grace_period_seconds.unwrap_or(120);
+
+    let commands = match shutdown.mode.clone().unwrap_or_default() {
+        PgBouncerShutdownMode::WaitForClients => "-c 'SHUTDOWN WAIT_FOR_CLIENTS'",
+        PgBouncerShutdownMode::Pause => "-c 'PAUSE' -c 'SHUTDOWN'",
+    };
+    let ssl_mode = match resource.spec.pg_bouncer.client_tls_ssl_mode {
+        PostgresSslMode::Disable => "disable",
+        PostgresSslMode::Allow | PostgresSslMode::Prefer => "prefer",
+        PostgresSslMode::Require | PostgresSslMode::VerifyCa | PostgresSslMode::VerifyFull => "require",
+    };
+    let mut connection = format!("host=127.0.0.1 port={PG_BOUNCER_PORT} dbname=pgbouncer user={OPERATOR_ADMIN_USER} sslmode={ssl_mode}");
+    if let Some((cert, key)) = admin_certificate_paths(tls_files) {
+        connection.push_str(&format!(" sslcert={cert} sslkey={key}"));
+    }
+
+    let drain_delay_seconds = shutdown.drain_delay_seconds.unwrap_or(10);
+    let wait_seconds = (termination_grace_period_seconds - i64::from(drain_delay_seconds)).max(0);
+    // The hook waits for pg_bouncer to exit, within the grace period, as the container is stopped
+    // as soon as it returns
+    let script = format!(
+        "touch {DRAIN_FILE}; sleep {drain_delay_seconds}; psql '{connection}' {commands}; \
+        i=0; while [ $i -lt {wait_seconds} ] && grep -qsx pgbouncer /proc/[0-9]*/comm; do sleep 1; i=$((i+1)); done",
+    );
+
+    Ok(Some(ShutdownHooks {
+        lifecycle: Lifecycle {
+            pre_stop: Some(LifecycleHandler {
+                exec: Some(ExecAction {
+                    command: Some(vec!["sh".to_string(), "-c".to_string(), script]),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        // Not ready while draining, or before pg_bouncer accepts connections
+        readiness_probe: Probe {
+            exec: Some(ExecAction {
+                command: Some(vec!["sh".to_string(), "-c".to_string(), format!("test ! -e {DRAIN_FILE} && pg_isready -q -h 127.0.0.1 -p {PG_BOUNCER_PORT}")]),
+            }),
+            period_seconds: Some(2),
+            failure_threshold: Some(1),
+            ..Default::default()
+        },
+        env: vec![EnvVar {
+            name: "PGPASSWORD".to_string(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: admin_secret_name(resource),
+                    key: ADMIN_PASSWORD_KEY.to_string(),
+                    optional: Some(false),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }],
+        termination_grace_period_seconds,
+    }))
+}
+
+/// The admin console can only be reached with a password, or with a client certificate issued
+/// by cert-manager, when clients have to present one.
+pub fn can_reload(resource: &PgBouncer, uses_hba: bool, tls_files: &TlsFiles) -> bool {
+    let settings = &resource.spec.pg_bouncer;
+    let needs_client_certificate = matches!(settings.client_tls_ssl_mode, PostgresSslMode::VerifyCa | PostgresSslMode::VerifyFull)
+        || (!uses_hba && settings.auth_type == PgBouncerAuthType::Cert);
+    !needs_client_certificate || tls_files.admin.is_some()
+}
+
+/// What the admin console of a pod reports once it has loaded the config. Passwords and hba
+/// rules cannot be read back, so they are not compared.
+#[derive(Default)]
+pub struct ExpectedConfig {
+    /// The `[pgbouncer]` settings, as reported by `SHOW CONFIG`.
+    pub settings: Vec<(String, String)>,
+    /// The columns of each database, as reported by `SHOW DATABASES`.
+    pub databases: BTreeMap<String, Vec<(&'static str, String)>>,
+    /// The columns of each user, as reported by `SHOW USERS`.
+    pub users: BTreeMap<String, Vec<(&'static str, String)>>,
+}
+
+impl ExpectedConfig {
+    /// Only the settings of databases and users that are configured are compared, as the
+    /// columns shown for the defaults differ between versions of pg_bouncer.
+    pub fn new(settings: Vec<(String, String)>, databases: &[&PgBouncerDatabaseSpec], users: &[&PgBouncerUserSpec], replicas: u32) -> Self {
+        let databases = databases.iter().map(|db| {
+            let name = db.exposed_database_name.clone();
+            let mut columns = vec![
+                ("host", db.host.clone()),
+                ("port", db.port.unwrap_or(DEFAULT_POSTGRES_PORT).to_string()),
+                ("database", db.internal_database_name.clone().unwrap_or_else(|| name.clone())),
+            ];
+            columns.extend([
+                ("force_user", db.user.clone()),
+                ("pool_size", db.pool_size.map(|v| v.to_string())),
+                ("min_pool_size", db.min_pool_size.map(|v| v.to_string())),
+                ("reserve_pool", db.reserve_pool.map(|v| v.to_string())),
+                ("pool_mode", db.pool_mode.as_ref().map(|v| v.to_string())),
+                ("max_connections", db.max_db_connections.map(|v| per_replica_connections(v, replicas).to_string())),
+            ].into_iter().filter_map(|(column, value)| value.map(|v| (column, v))));
+            (name, columns)
+        }).collect();
+
+        let users = users.iter().map(|user| {
+            let columns = [
+                ("pool_mode", user.pool_mode.as_ref().map(|v| v.to_string())),
+                ("max_user_connections", user.max_user_connections.map(|v| v.to_string())),
+                ("pool_size", user.pool_size.map(|v| v.to_string())),
+            ].into_iter().filter_map(|(column, value)| value.map(|v| (column, v))).collect();
+            (user.username.clone(), columns)
+        }).collect();
+
+        ExpectedConfig { settings, databases, users }
+    }
+}
+
+/// pg_bouncer shows some numbers differently than they are configured, like `1e+06`.
+fn values_match(expected: &str, loaded: &str) -> bool {
+    expected == loaded || matches!((expected.parse::<f64>(), loaded.parse::<f64>()), (Ok(e), Ok(l)) if e == l)
+}
+
+async fn show(pg_connection: &tokio_postgres::Client, command: &str) -> anyhow::Result<Vec<SimpleQueryRow>> {
+    Ok(pg_connection.simple_query(command).await?
+        .into_iter()
+        .filter_map(|m| match m {
+            SimpleQueryMessage::Row(row) => Some(row),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Compares the rows with the expected columns, by their `name` column. Columns the version of
+/// pg_bouncer does not show are skipped.
+fn compare_rows(kind: &str, rows: &[SimpleQueryRow], expected: &BTreeMap<String, Vec<(&'static str, String)>>, differences: &mut Vec<String>) {
+    for (name, columns) in expected {
+        let Some(row) = rows.iter().find(|r| r.try_get("name").ok().flatten() == Some(name.as_str())) else {
+            differences.push(format!("{} {} is not loaded", kind, name));
+            continue;
+        };
+        for (column, value) in columns {
+            if let Ok(loaded) = row.try_get(*column) {
+                let loaded = loaded.unwrap_or_default();
+                if !values_match(value, loaded) {
+                    differences.push(format!("{} {} has {} {:?} instead of {:?}", kind, name, column, loaded, value));
+                }
+            }
+        }
+    }
+}
+
+/// Connects to the admin console of a pod, with TLS unless clients are not required to use it.
+pub async fn connect_to_admin_console(pod_ip: &str, resource: &PgBouncer, admin_password: &str, tls_files: &TlsFiles) -> anyhow::Result<tokio_postgres::Client> {
+    // Pods are connected to by their IP, which their certificates are not issued for, so like
+    // `sslmode=require` the certificate is not verified
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyServerCertificate));
+    let tls_config = match &tls_files.admin {
+        Some(admin) => {
+            let certs = rustls_pemfile::certs(&mut admin.cert_pem.as_slice())
+                .map(|c| c.map(|c| rustls::Certificate(c.to_vec())))
+                .collect::<Result<Vec<_>, _>>()?;
+            let Some(key) = rustls_pemfile::private_key(&mut admin.key_pem.as_slice())? else {
+                bail!("The admin certificate has no private key");
+            };
+            builder.with_client_auth_cert(certs, rustls::PrivateKey(key.secret_der().to_vec()))?
+        },
+        None => builder.with_no_client_auth(),
+    };
+
+    let ssl_mode = match resource.spec.pg_bouncer.client_tls_ssl_mode {
+        PostgresSslMode::Disable => SslMode::Disable,
+        PostgresSslMode::Allow | PostgresSslMode::Prefer => SslMode::Prefer,
+        PostgresSslMode::Require | PostgresSslMode::VerifyCa | PostgresSslMode::VerifyFull => SslMode::Require,
+    };
+
+    let (client, connection) = tokio_postgres::Config::new()
+        .host(pod_ip)
+        .port(PG_BOUNCER_PORT as u16)
+        .user(OPERATOR_ADMIN_USER)
+        .password(admin_password)
+        .dbname("pgbouncer")
+        .ssl_mode(ssl_mode)
+        .connect(MakeRustlsConnect::new(tls_config)).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            error!("admin console connection error: {}", e);
+        }
+    });
+
+    Ok(client)
+}
+
+struct AcceptAnyServerCertificate;
+
+impl ServerCertVerifier for AcceptAnyServerCertificate {
+    fn verify_server_cert(&self, _end_entity: &rustls::Certificate, _intermediates: &[rustls::Certificate], _server_name: &rustls::ServerName, _scts: &mut dyn Iterator<Item=&[u8]>, _ocsp_response: &[u8], _now: SystemTime) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Optionally reloads the config of a pod, and returns how the loaded config differs from the
+/// expected config.
+pub async fn reload_pod(pg_connection: &tokio_postgres::Client, reload: bool, expected: &ExpectedConfig) -> anyhow::Result<Vec<String>> {
+    // The admin console only supports the simple query protocol
+    if reload {
+        pg_connection.simple_query("RELOAD").await?;
+    }
+
+    let mut differences = vec![];
+
+    let config = show(pg_connection, "SHOW CONFIG").await?;
+    for (key, value) in &expected.settings {
+        let Some(row) = config.iter().find(|r| r.try_get("key").ok().flatten() == Some(key.as_str())) else {
+            continue;
+        };
+        // Settings that need a restart are rolled out with new pods
+        if row.try_get("changeable").ok().flatten() == Some("no") {
+            continue;
+        }
+        let loaded = row.try_get("value")?.unwrap_or_default();
+        if !values_match(value, loaded) {
+            differences.push(format!("{} is {:?} instead of {:?}", key, loaded, value));
+        }
+    }
+
+    let databases = show(pg_connection, "SHOW DATABASES").await?;
+    compare_rows("Database", &databases, &expected.databases, &mut differences);
+    for row in &databases {
+        let name = row.try_get("name")?.unwrap_or_default();
+        if name != "pgbouncer" && !expected.databases.contains_key(name) {
+            differences.push(format!("Database {} is still loaded", name));
+        }
+    }
+
+    let users = show(pg_connection, "SHOW USERS").await?;
+    compare_rows("User", &users, &expected.users, &mut differences);
+
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{PgBouncerDatabaseSpec, PgBouncerReference};
+    use super::ExpectedConfig;
+
+    #[test]
+    fn test_expected_config() {
+        assert!(super::values_match("1000000", "1e+06"));
+        assert!(!super::values_match("session", "transaction"));
+
+        let db = PgBouncerDatabaseSpec {
+            exposed_database_name: "app".to_string(),
+            internal_database_name: None,
+            host: "postgres".to_string(),
+            port: None,
+            user: None,
+            pg_bouncer: PgBouncerReference::default(),
+            pool_size: Some(5),
+            min_pool_size: None,
+            reserve_pool: None,
+            pool_mode: None,
+            max_db_connections: Some(10),
+            connect_query: None,
+            client_encoding: None,
+            datestyle: None,
+            timezone: None,
+            auth_user: None,
+            connection: None,
+            hba_rules: None,
+        };
+
+        let expected = ExpectedConfig::new(vec![], &[&db], &[], 2);
+        assert_eq!(expected.databases["app"], vec![
+            ("host", "postgres".to_string()),
+            ("port", "5432".to_string()),
+            ("database", "app".to_string()),
+            ("pool_size", "5".to_string()),
+            ("max_connections", "5".to_string()),
+        ]);
+    }
+}
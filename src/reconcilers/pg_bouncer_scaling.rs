@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use anyhow::bail;
+use k8s_openapi::api::autoscaling::v2::{CrossVersionObjectReference, HorizontalPodAutoscaler, HorizontalPodAutoscalerSpec, MetricSpec, MetricTarget, ResourceMetricSource};
+use k8s_openapi::api::policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::{Api, Resource};
+use kube::api::{DeleteParams, Patch, PatchParams};
+use serde::de::DeserializeOwned;
+use crate::types::{PgBouncer, PgBouncerSpec};
+
+/// The replicas of the deployment, which are left to the autoscaler when there is one.
+pub fn deployment_replicas(spec: &PgBouncerSpec) -> Option<i32> {
+    match spec.autoscaling {
+        Some(_) => None,
+        None => Some(spec.replicas.unwrap_or(1)),
+    }
+}
+
+/// The most replicas there can be.
+pub fn connection_replicas(spec: &PgBouncerSpec) -> u32 {
+    let replicas = match &spec.autoscaling {
+        Some(autoscaling) => autoscaling.max_replicas,
+        None => spec.replicas.unwrap_or(1),
+    };
+    replicas.max(1) as u32
+}
+
+/// Divides a connection limit between the replicas, so scaling out does not exceed the limit on
+/// the server. 0 is unlimited.
+pub fn per_replica_connections(connections: u32, replicas: u32) -> u32 {
+    if connections == 0 {
+        return 0;
+    }
+
+    (connections / replicas).max(1)
+}
+
+pub async fn apply_autoscaler(resource: &PgBouncer, namespace: &str, deployment_name: &str, labels: &BTreeMap<String, String>, serverside: &PatchParams, kubernetes_client: kube::Client) -> anyhow::Result<()> {
+    let autoscaler_api: Api<HorizontalPodAutoscaler> = Api::namespaced(kubernetes_client, namespace);
+
+    let Some(autoscaling) = &resource.spec.autoscaling else {
+        return delete_if_exists(&autoscaler_api, deployment_name).await;
+    };
+
+    let min_replicas = autoscaling.min_replicas.unwrap_or(1);
+    if min_replicas < 1 || autoscaling.max_replicas < min_replicas {
+        bail!("The autoscaler needs at least 1 replica, and maxReplicas of at least minReplicas");
+    }
+
+    let target_cpu = match (autoscaling.target_cpu_utilization_percentage, autoscaling.target_memory_utilization_percentage) {
+        (None, None) => Some(80),
+        (cpu, _) => cpu,
+    };
+    let targets = [("cpu", target_cpu), ("memory", autoscaling.target_memory_utilization_percentage)];
+
+    // Utilization is relative to the requests, without which the autoscaler cannot compute it
+    let requests = resource.spec.pod_options.as_ref()
+        .and_then(|o| o.resources.as_ref())
+        .and_then(|r| r.requests.as_ref());
+    for (name, utilization) in &targets {
+        if utilization.is_some() && !requests.is_some_and(|r| r.contains_key(*name)) {
+            bail!("The autoscaler targets {} utilization, which needs a {} request in podOptions.resources", name, name);
+        }
+    }
+
+    let metrics = targets
+        .into_iter()
+        .filter_map(|(name, utilization)| utilization.map(|u| MetricSpec {
+            type_: "Resource".to_string(),
+            resource: Some(ResourceMetricSource {
+                name: name.to_string(),
+                target: MetricTarget {
+                    type_: "Utilization".to_string(),
+                    average_utilization: Some(u),
+                    ..Default::default()
+                },
+            }),
+            ..Default::default()
+        }))
+        .collect();
+
+    let autoscaler = HorizontalPodAutoscaler {
+        metadata: ObjectMeta {
+            namespace: Some(namespace.to_string()),
+            name: Some(deployment_name.to_string()),
+            owner_references: Some(vec![resource.controller_owner_ref(&()).unwrap()]),
+            labels: Some(labels.clone()),
+            ..Default::default()
+        },
+        spec: Some(HorizontalPodAutoscalerSpec {
+            scale_target_ref: CrossVersionObjectReference {
+                api_version: Some("apps/v1".to_string()),
+                kind: "Deployment".to_string(),
+                name: deployment_name.to_string(),
+            },
+            min_replicas: Some(min_replicas),
+            max_replicas: autoscaling.max_replicas,
+            metrics: Some(metrics),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    autoscaler_api.patch(deployment_name, serverside, &Patch::Apply(autoscaler)).await?;
+
+    Ok(())
+}
+
+pub async fn apply_pod_disruption_budget(resource: &PgBouncer, namespace: &str, name: &str, labels: &BTreeMap<String, String>, pod_labels: &BTreeMap<String, String>, serverside: &PatchParams, kubernetes_client: kube::Client) -> anyhow::Result<()> {
+    let budget_api: Api<PodDisruptionBudget> = Api::namespaced(kubernetes_client, namespace);
+
+    let Some(budget) = &resource.spec.pod_disruption_budget else {
+        return delete_if_exists(&budget_api, name).await;
+    };
+
+    let (min_available, max_unavailable) = match (budget.min_available.clone(), budget.max_unavailable.clone()) {
+        (Some(_), Some(_)) => bail!("Only one of minAvailable and maxUnavailable can be set on the pod disruption budget"),
+        (None, None) => (None, Some(IntOrString::Int(1))),
+        limits => limits,
+    };
+
+    let budget = PodDisruptionBudget {
+        metadata: ObjectMeta {
+            namespace: Some(namespace.to_string()),
+            name: Some(name.to_string()),
+            owner_references: Some(vec![resource.controller_owner_ref(&()).unwrap()]),
+            labels: Some(labels.clone()),
+            ..Default::default()
+        },
+        spec: Some(PodDisruptionBudgetSpec {
+            selector: Some(LabelSelector {
+                match_labels: Some(pod_labels.clone()),
+                ..Default::default()
+            }),
+            min_available,
+            max_unavailable,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    budget_api.patch(name, serverside, &Patch::Apply(budget)).await?;
+
+    Ok(())
+}
+
+async fn delete_if_exists<K: Resource + Clone + DeserializeOwned + Debug>(api: &Api<K>, name: &str) -> anyhow::Result<()> {
+    match api.delete(name, &DeleteParams::default()).await {
+        Ok(_) => {
+            info!("Deleted {}", name);
+            Ok(())
+        }
+        Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
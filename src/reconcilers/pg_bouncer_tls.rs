@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use anyhow::bail;
+use k8s_openapi::api::core::v1::{KeyToPath, Secret, SecretProjection, VolumeProjection};
+use kube::{Api, Resource, ResourceExt};
+use kube::api::{ApiResource, DynamicObject, GroupVersionKind, Patch, PatchParams};
+use serde_json::json;
+use sha2::Digest;
+use crate::helpers::ini_builder::IniBuilder;
+use crate::reconcilers::pg_bouncer_admin::OPERATOR_ADMIN_USER;
+use crate::types::{PgBouncer, PgBouncerCertificate, PgBouncerSettings, PostgresSslMode};
+
+const TLS_KEY: &str = "tls.key";
+const TLS_CERT: &str = "tls.crt";
+const TLS_CA: &str = "ca.crt";
+const ADMIN: &str = "admin";
+
+/// The keys present in a TLS secret.
+pub struct TlsSecretFiles {
+    secret_name: String,
+    keys: Vec<&'static str>,
+}
+
+impl TlsSecretFiles {
+    fn has(&self, key: &str) -> bool {
+        self.keys.contains(&key)
+    }
+}
+
+/// The client certificate the operator presents to the admin console, when clients have to
+/// present one.
+pub struct AdminCertificate {
+    files: TlsSecretFiles,
+    pub cert_pem: Vec<u8>,
+    pub key_pem: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct TlsFiles {
+    client: Option<TlsSecretFiles>,
+    server: Option<TlsSecretFiles>,
+    pub admin: Option<AdminCertificate>,
+    /// Changes when any of the secrets change, so the pods can be rolled.
+    pub hash: Option<String>,
+}
+
+/// Returns `None` when a secret does not exist yet, for example while cert-manager is issuing
+/// the certificate.
+pub async fn get_tls_files(resource: &PgBouncer, namespace: &str, serverside: &PatchParams, kubernetes_client: kube::Client) -> anyhow::Result<Option<TlsFiles>> {
+    let Some(tls) = &resource.spec.tls else {
+        return Ok(Some(TlsFiles::default()));
+    };
+
+    let client_secret_name = match (&tls.client_secret_name, &tls.certificate) {
+        (Some(_), Some(_)) => bail!("Only one of clientSecretName and certificate can be given"),
+        (Some(secret_name), None) => Some(secret_name.clone()),
+        (None, Some(certificate)) => Some(apply_client_certificate(resource, certificate, namespace, serverside, kubernetes_client.clone()).await?),
+        (None, None) => None,
+    };
+
+    let secret_api: Api<Secret> = Api::namespaced(kubernetes_client.clone(), namespace);
+
+    // Clients have to present a certificate signed by the client CA, which is the issuer of the
+    // client certificate when cert-manager is used
+    let verifies_clients = matches!(resource.spec.pg_bouncer.client_tls_ssl_mode, PostgresSslMode::VerifyCa | PostgresSslMode::VerifyFull);
+    let admin = match &tls.certificate {
+        Some(certificate) if verifies_clients => {
+            let secret_name = apply_admin_certificate(resource, certificate, namespace, serverside, kubernetes_client).await?;
+            match get_admin_certificate(&secret_api, secret_name).await? {
+                Some(admin) => Some(admin),
+                None => return Ok(None),
+            }
+        },
+        _ => None,
+    };
+    let mut hasher = sha2::Sha256::new();
+
+    let client = match client_secret_name {
+        Some(secret_name) => match get_secret_files(&secret_api, secret_name, &mut hasher).await? {
+            Some(files) => Some(files),
+            None => return Ok(None),
+        },
+        None => None,
+    };
+    let server = match &tls.server_secret_name {
+        Some(secret_name) => match get_secret_files(&secret_api, secret_name.clone(), &mut hasher).await? {
+            Some(files) => Some(files),
+            None => return Ok(None),
+        },
+        None => None,
+    };
+
+    Ok(Some(TlsFiles {
+        client,
+        server,
+        admin,
+        hash: Some(base16ct::lower::encode_string(&hasher.finalize())),
+    }))
+}
+
+async fn get_secret_files(secret_api: &Api<Secret>, secret_name: String, hasher: &mut sha2::Sha256) -> anyhow::Result<Option<TlsSecretFiles>> {
+    let Some(secret) = secret_api.get_opt(&secret_name).await? else {
+        error!("TLS secret {} not found", secret_name);
+        return Ok(None);
+    };
+
+    let data = secret.data.unwrap_or_default();
+    for (key, value) in &data {
+        hasher.update(key.as_bytes());
+        hasher.update(&value.0);
+    }
+
+    let keys = [TLS_KEY, TLS_CERT, TLS_CA].into_iter().filter(|k| data.contains_key(*k)).collect();
+    Ok(Some(TlsSecretFiles { secret_name, keys }))
+}
+
+async fn get_admin_certificate(secret_api: &Api<Secret>, secret_name: String) -> anyhow::Result<Option<AdminCertificate>> {
+    let Some(secret) = secret_api.get_opt(&secret_name).await? else {
+        error!("TLS secret {} not found", secret_name);
+        return Ok(None);
+    };
+
+    let mut data = secret.data.unwrap_or_default();
+    let (Some(cert), Some(key)) = (data.remove(TLS_CERT), data.remove(TLS_KEY)) else {
+        error!("TLS secret {} has no certificate yet", secret_name);
+        return Ok(None);
+    };
+
+    Ok(Some(AdminCertificate {
+        files: TlsSecretFiles { secret_name, keys: vec![TLS_CERT, TLS_KEY] },
+        cert_pem: cert.0,
+        key_pem: key.0,
+    }))
+}
+
+/// Creates a cert-manager Certificate for the service, and returns the name of its secret.
+async fn apply_client_certificate(resource: &PgBouncer, certificate: &PgBouncerCertificate, namespace: &str, serverside: &PatchParams, kubernetes_client: kube::Client) -> anyhow::Result<String> {
+    let service_name = &resource.spec.service.name;
+
+    let mut dns_names = vec![
+        service_name.clone(),
+        format!("{}.{}", service_name, namespace),
+        format!("{}.{}.svc", service_name, namespace),
+        format!("{}.{}.svc.cluster.local", service_name, namespace),
+    ];
+    dns_names.extend(certificate.dns_names.iter().flatten().cloned());
+
+    let name = format!("{}-client-tls", resource.name_any());
+    apply_certificate(resource, certificate, &name, json!({ "dnsNames": dns_names }), namespace, serverside, kubernetes_client).await?;
+
+    Ok(name)
+}
+
+/// Creates a cert-manager Certificate for the operator admin user, and returns the name of its
+/// secret.
+async fn apply_admin_certificate(resource: &PgBouncer, certificate: &PgBouncerCertificate, namespace: &str, serverside: &PatchParams, kubernetes_client: kube::Client) -> anyhow::Result<String> {
+    let name = format!("{}-admin-tls", resource.name_any());
+    let spec = json!({
+        "commonName": OPERATOR_ADMIN_USER,
+        "usages": ["client auth", "digital signature", "key encipherment"],
+        "privateKey": { "encoding": "PKCS8" },
+    });
+    apply_certificate(resource, certificate, &name, spec, namespace, serverside, kubernetes_client).await?;
+
+    Ok(name)
+}
+
+async fn apply_certificate(resource: &PgBouncer, certificate: &PgBouncerCertificate, name: &str, mut spec: serde_json::Value, namespace: &str, serverside: &PatchParams, kubernetes_client: kube::Client) -> anyhow::Result<()> {
+    spec["secretName"] = json!(name);
+    spec["issuerRef"] = json!({
+        "name": certificate.issuer_name,
+        "kind": certificate.issuer_kind.as_deref().unwrap_or("Issuer"),
+        "group": certificate.issuer_group.as_deref().unwrap_or("cert-manager.io"),
+    });
+
+    let api_resource = ApiResource::from_gvk(&GroupVersionKind::gvk("cert-manager.io", "v1", "Certificate"));
+    let mut desired = DynamicObject::new(name, &api_resource)
+        .within(namespace)
+        .data(json!({ "spec": spec }));
+    desired.metadata.owner_references = Some(vec![resource.controller_owner_ref(&()).unwrap()]);
+
+    let certificate_api: Api<DynamicObject> = Api::namespaced_with(kubernetes_client, namespace, &api_resource);
+    certificate_api.patch(name, serverside, &Patch::Apply(&desired)).await?;
+
+    Ok(())
+}
+
+pub fn add_tls_settings(builder: &mut IniBuilder, settings: &PgBouncerSettings, files: &TlsFiles, uses_cert_auth: bool) -> anyhow::Result<()> {
+    let client = files.client.as_ref();
+    let server = files.server.as_ref();
+
+    if uses_cert_auth {
+        if !client.is_some_and(|c| c.has(TLS_CA)) {
+            bail!("Certificate authentication requires a client CA");
+        }
+        if !matches!(settings.client_tls_ssl_mode, PostgresSslMode::VerifyCa | PostgresSslMode::VerifyFull) {
+            bail!("Certificate authentication requires a client TLS mode of verify-ca or verify-full, not {}", settings.client_tls_ssl_mode);
+        }
+    }
+
+    if settings.client_tls_ssl_mode != PostgresSslMode::Disable && !client.is_some_and(|c| c.has(TLS_KEY) && c.has(TLS_CERT)) {
+        bail!("Client TLS mode {} requires a client key and certificate", settings.client_tls_ssl_mode);
+    }
+    if matches!(settings.server_tls_ssl_mode, PostgresSslMode::VerifyCa | PostgresSslMode::VerifyFull) && !server.is_some_and(|s| s.has(TLS_CA)) {
+        bail!("Server TLS mode {} requires a server CA", settings.server_tls_ssl_mode);
+    }
+
+    for (prefix, files) in [("client", client), ("server", server)] {
+        for (setting, key) in [("key_file", TLS_KEY), ("cert_file", TLS_CERT), ("ca_file", TLS_CA)] {
+            if files.is_some_and(|f| f.has(key)) {
+                builder.add_setting(&format!("{}_tls_{}", prefix, setting), format!("/etc/pgbouncer/{}", tls_path(prefix, key)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The TLS files are projected into the config volume, under `tls/client`, `tls/server` and
+/// `tls/admin`.
+pub fn tls_volume_projections(files: &TlsFiles) -> Vec<VolumeProjection> {
+    [("client", files.client.as_ref()), ("server", files.server.as_ref()), (ADMIN, files.admin.as_ref().map(|a| &a.files))].into_iter()
+        .filter_map(|(prefix, files)| files.map(|f| (prefix, f)))
+        .map(|(prefix, files)| VolumeProjection {
+            secret: Some(SecretProjection {
+                name: files.secret_name.clone(),
+                items: Some(files.keys.iter().map(|key| KeyToPath {
+                    key: key.to_string(),
+                    path: tls_path(prefix, key),
+                    // libpq refuses keys readable by others
+                    mode: (prefix == ADMIN && *key == TLS_KEY).then_some(0o640),
+                }).collect()),
+                optional: Some(false),
+            }),
+            ..Default::default()
+        })
+        .collect()
+}
+
+pub fn tls_annotations(files: &TlsFiles) -> Option<BTreeMap<String, String>> {
+    files.hash.as_ref().map(|hash| BTreeMap::from([("postgres-topology-operator/tls_hash".to_string(), hash.clone())]))
+}
+
+/// The paths of the admin certificate and key in the pod.
+pub fn admin_certificate_paths(files: &TlsFiles) -> Option<(String, String)> {
+    files.admin.as_ref().map(|_| (
+        format!("/etc/pgbouncer/{}", tls_path(ADMIN, TLS_CERT)),
+        format!("/etc/pgbouncer/{}", tls_path(ADMIN, TLS_KEY)),
+    ))
+}
+
+fn tls_path(prefix: &str, key: &str) -> String {
+    format!("tls/{}/{}", prefix, key)
+}
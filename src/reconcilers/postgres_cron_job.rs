@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::time::Duration;
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::get_postgres_connection;
+use crate::types::{PostgresCronJob, PostgresRole};
+
+pub async fn reconcile_postgres_cron_job(resource: Arc<PostgresCronJob>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresCronJob>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres cron job {:?}", resource.metadata.name);
+
+    let job_name = &resource.spec.job_name;
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres cron job {:?}", resource.metadata.name);
+
+        let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+        pg_connection.execute("SELECT cron.unschedule(jobid) FROM cron.job WHERE jobname = $1", &[&job_name]).await?;
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+
+    let pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+
+    let username = if let Some(role_reference) = &resource.spec.run_as {
+        let role_api: Api<PostgresRole> = Api::namespaced(context.kubernetes_client.clone(), role_reference.namespace.as_ref().unwrap_or(&namespace));
+
+        if let Some(role) = role_api.get_opt(&role_reference.name).await? {
+            role.spec.role
+        } else {
+            error!("Role {} not found", role_reference.name);
+            return Ok(Action::requeue(Duration::from_secs(30)));
+        }
+    } else {
+        pg_connection.admin_username.clone()
+    };
+    let database = resource.spec.database.clone().unwrap_or_else(|| pg_connection.database.clone());
+    let active = resource.spec.active.unwrap_or(true);
+
+    let existing = pg_connection.query_opt("SELECT jobid, schedule, command, database, username, active FROM cron.job WHERE jobname = $1", &[&job_name]).await?;
+
+    let job_id: i64 = match existing {
+        Some(existing) if existing.get::<_, &str>(1) == resource.spec.schedule
+            && existing.get::<_, &str>(2) == resource.spec.command
+            && existing.get::<_, &str>(3) == database
+            && existing.get::<_, &str>(4) == username
+            && existing.get::<_, bool>(5) == active => {
+            debug!("Cron job {} is up to date", job_name);
+            existing.get(0)
+        },
+        existing => {
+            // Jobs are scheduled per user, so a job owned by another user would not be replaced
+            if let Some(existing) = existing.filter(|e| e.get::<_, &str>(4) != username) {
+                let existing_job_id: i64 = existing.get(0);
+                info!("Unscheduling cron job {} owned by {}", job_name, existing.get::<_, &str>(4));
+                pg_connection.execute("SELECT cron.unschedule($1::bigint)", &[&existing_job_id]).await?;
+            }
+
+            info!("Scheduling cron job {} at {}", job_name, resource.spec.schedule);
+            pg_connection.query_one(
+                "SELECT cron.schedule_in_database($1, $2, $3, $4, $5, $6)",
+                &[job_name, &resource.spec.schedule, &resource.spec.command, &database, &username, &active],
+            ).await?.get(0)
+        },
+    };
+
+    let last_run = pg_connection.query_opt("
+        SELECT status, return_message, start_time::text, end_time::text
+        FROM cron.job_run_details
+        WHERE jobid = $1
+        ORDER BY runid DESC
+        LIMIT 1", &[&job_id]).await?;
+
+    let (last_run_status, last_run_message, last_run_start_time, last_run_end_time): (Option<String>, Option<String>, Option<String>, Option<String>) = match &last_run {
+        Some(row) => (row.get(0), row.get(1), row.get(2), row.get(3)),
+        None => (None, None, None, None),
+    };
+
+    let status = json!({
+        "status": {
+            "jobId": job_id,
+            "lastRunStatus": last_run_status,
+            "lastRunMessage": last_run_message,
+            "lastRunStartTime": last_run_start_time,
+            "lastRunEndTime": last_run_end_time,
+        }
+    });
+
+    let cron_job_api: Api<PostgresCronJob> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    cron_job_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    info!("Postgres cron job {} reconciled", job_name);
+
+    // Requeue to keep the outcome of the last run up to date
+    Ok(Action::requeue(Duration::from_secs(60)))
+}
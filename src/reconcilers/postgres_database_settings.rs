@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use anyhow::bail;
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::{get_postgres_connection, quote_literal};
+use crate::types::PostgresDatabaseSettings;
+
+/// The parameters Postgres parses as lists, which can be set at the database level.
+const LIST_PARAMETERS: [&str; 4] = ["search_path", "temp_tablespaces", "local_preload_libraries", "session_preload_libraries"];
+
+pub async fn reconcile_postgres_database_settings(resource: Arc<PostgresDatabaseSettings>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresDatabaseSettings>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres database settings {:?}", resource.metadata.name);
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres database settings {:?}", resource.metadata.name);
+
+        let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+        let database = resource.spec.database.clone().unwrap_or_else(|| pg_connection.database.clone());
+
+        if pg_connection.query_opt("SELECT 1 FROM pg_database WHERE datname = $1", &[&database]).await?.is_some() {
+            let managed_parameters = resource.status.as_ref().and_then(|s| s.managed_parameters.clone()).unwrap_or_default();
+            for parameter in managed_parameters {
+                pg_connection.execute(&format!("ALTER DATABASE {} RESET {}", database, parameter), &[]).await?;
+            }
+            if resource.spec.allow_connections.is_some() {
+                pg_connection.execute(&format!("ALTER DATABASE {} WITH ALLOW_CONNECTIONS true", database), &[]).await?;
+            }
+            if resource.spec.connection_limit.is_some() {
+                pg_connection.execute(&format!("ALTER DATABASE {} WITH CONNECTION LIMIT -1", database), &[]).await?;
+            }
+        }
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+    let pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+    let database = resource.spec.database.clone().unwrap_or_else(|| pg_connection.database.clone());
+
+    if resource.spec.allow_connections == Some(false) && database == pg_connection.database {
+        bail!("Cannot disallow connections to {}, as the admin connection uses that database", database);
+    }
+
+    let Some(existing) = pg_connection.query_opt("SELECT datallowconn, datconnlimit FROM pg_database WHERE datname = $1", &[&database]).await? else {
+        bail!("Database {} does not exist", database);
+    };
+    let existing_allow_connections: bool = existing.get(0);
+    let existing_connection_limit: i32 = existing.get(1);
+
+    let existing_parameters: BTreeMap<String, String> = pg_connection.query("
+        SELECT unnest(s.setconfig)
+        FROM pg_db_role_setting s
+            JOIN pg_database d ON d.oid = s.setdatabase
+        WHERE s.setrole = 0 AND d.datname = $1", &[&database]).await?
+        .iter()
+        .filter_map(|row| row.get::<_, String>(0).split_once('=').map(|(k, v)| (k.to_string(), v.to_string())))
+        .collect();
+
+    let parameters = resource.spec.parameters.clone().unwrap_or_default();
+
+    for (parameter, value) in &parameters {
+        // List parameters are passed as one literal per element, which is how Postgres stores them.
+        // Other values can contain commas themselves, so they are passed as a single literal
+        let elements: Vec<&str> = if LIST_PARAMETERS.contains(&parameter.to_lowercase().as_str()) {
+            value.split(',').map(|e| e.trim()).collect()
+        } else {
+            vec![value.as_str()]
+        };
+        if existing_parameters.get(parameter) == Some(&elements.join(", ")) {
+            continue;
+        }
+
+        info!("Setting {} to {} on database {}", parameter, value, database);
+        let literals = elements.iter().map(|e| quote_literal(e)).collect::<Vec<_>>().join(", ");
+        pg_connection.execute(&format!("ALTER DATABASE {} SET {} = {}", database, parameter, literals), &[]).await?;
+    }
+
+    let previously_managed = resource.status.as_ref().and_then(|s| s.managed_parameters.clone()).unwrap_or_default();
+    for parameter in previously_managed.iter().filter(|p| !parameters.contains_key(*p)) {
+        info!("Resetting {} on database {}", parameter, database);
+        pg_connection.execute(&format!("ALTER DATABASE {} RESET {}", database, parameter), &[]).await?;
+    }
+
+    if let Some(connection_limit) = resource.spec.connection_limit {
+        if connection_limit != existing_connection_limit {
+            info!("Setting connection limit of database {} to {}", database, connection_limit);
+            pg_connection.execute(&format!("ALTER DATABASE {} WITH CONNECTION LIMIT {}", database, connection_limit), &[]).await?;
+        }
+    }
+
+    let mut terminated_backends = resource.status.as_ref().and_then(|s| s.terminated_backends);
+    let mut backends_terminated = None;
+    if let Some(allow_connections) = resource.spec.allow_connections {
+        if allow_connections != existing_allow_connections {
+            info!("Setting allow connections of database {} to {}", database, allow_connections);
+            pg_connection.execute(&format!("ALTER DATABASE {} WITH ALLOW_CONNECTIONS {}", database, allow_connections), &[]).await?;
+        }
+
+        // Existing connections are not affected by the setting, and are terminated until that
+        // succeeds once
+        if !allow_connections {
+            if existing_allow_connections || resource.status.as_ref().and_then(|s| s.backends_terminated) != Some(true) {
+                let terminated: i64 = pg_connection.query_one(
+                    "SELECT count(*) FILTER (WHERE pg_terminate_backend(pid)) FROM pg_stat_activity WHERE datname = $1 AND pid <> pg_backend_pid()",
+                    &[&database]
+                ).await?.get(0);
+                info!("Terminated {} backends connected to database {}", terminated, database);
+                terminated_backends = Some(terminated);
+            }
+            backends_terminated = Some(true);
+        }
+    }
+
+    let status = json!({
+        "status": {
+            "ready": true,
+            "managedParameters": parameters.keys().collect::<Vec<_>>(),
+            "terminatedBackends": terminated_backends,
+            "backendsTerminated": backends_terminated,
+        }
+    });
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let settings_api: Api<PostgresDatabaseSettings> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    settings_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    info!("Postgres database settings of {} reconciled", database);
+
+    Ok(Action::await_change())
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::{get_postgres_connection, quote_literal};
+use crate::types::PostgresForeignServer;
+
+pub async fn reconcile_postgres_foreign_server(resource: Arc<PostgresForeignServer>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresForeignServer>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres foreign server {:?}", resource.metadata.name);
+
+    let server_name = &resource.spec.server_name;
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres foreign server {:?}", resource.metadata.name);
+
+        // Also drops the user mappings and foreign tables of the server
+        let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+        pg_connection.execute(&format!("DROP SERVER IF EXISTS {} CASCADE", server_name), &[]).await?;
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+    let mut options = resource.spec.options.clone().unwrap_or_default();
+    options.insert("host".to_string(), resource.spec.host.clone());
+    options.insert("dbname".to_string(), resource.spec.dbname.clone());
+    if let Some(port) = resource.spec.port {
+        options.insert("port".to_string(), port.to_string());
+    }
+
+    let pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+
+    pg_connection.execute("CREATE EXTENSION IF NOT EXISTS postgres_fdw", &[]).await?;
+
+    if let Some(existing) = pg_connection.query_opt("SELECT srvoptions FROM pg_foreign_server WHERE srvname = $1", &[&server_name]).await? {
+        let existing: Option<Vec<String>> = existing.get(0);
+        let clauses = alter_options_clauses(&existing.unwrap_or_default(), &options);
+
+        if clauses.is_empty() {
+            info!("Foreign server {} is up to date", server_name);
+        } else {
+            info!("Updating options of foreign server {}", server_name);
+            pg_connection.execute(&format!("ALTER SERVER {} OPTIONS ({})", server_name, clauses.join(", ")), &[]).await?;
+        }
+    } else {
+        info!("Creating foreign server {}", server_name);
+        let options = options.iter()
+            .map(|(key, value)| format!("{} {}", key, quote_literal(value)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        pg_connection.execute(&format!("CREATE SERVER {} FOREIGN DATA WRAPPER postgres_fdw OPTIONS ({})", server_name, options), &[]).await?;
+    }
+
+    let status = json!({
+        "status": {
+            "ready": true
+        }
+    });
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let server_api: Api<PostgresForeignServer> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    server_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    info!("Postgres foreign server {} reconciled", server_name);
+
+    Ok(Action::await_change())
+}
+
+/// Creates the `ADD`, `SET` and `DROP` clauses of an `OPTIONS (...)` list, that changes the
+/// existing options, as stored by Postgres in `key=value` form, into the desired options.
+pub fn alter_options_clauses(existing: &[String], desired: &BTreeMap<String, String>) -> Vec<String> {
+    let existing: BTreeMap<&str, &str> = existing.iter()
+        .filter_map(|option| option.split_once('='))
+        .collect();
+
+    let mut clauses = vec![];
+
+    for (key, value) in desired {
+        match existing.get(key.as_str()) {
+            Some(existing_value) if existing_value == value => {},
+            Some(_) => clauses.push(format!("SET {} {}", key, quote_literal(value))),
+            None => clauses.push(format!("ADD {} {}", key, quote_literal(value))),
+        }
+    }
+
+    for key in existing.keys() {
+        if !desired.contains_key(*key) {
+            clauses.push(format!("DROP {}", key));
+        }
+    }
+
+    clauses
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_alter_options_clauses() {
+        let existing = vec!["host=old-host".to_string(), "dbname=app".to_string(), "fetch_size=100".to_string()];
+        let desired = BTreeMap::from([
+            ("host".to_string(), "new-host".to_string()),
+            ("dbname".to_string(), "app".to_string()),
+            ("port".to_string(), "5432".to_string()),
+        ]);
+
+        let clauses = super::alter_options_clauses(&existing, &desired);
+
+        assert_eq!(clauses, vec!["SET host 'new-host'", "ADD port '5432'", "DROP fetch_size"]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, bail};
+use cron::Schedule;
+use k8s_openapi::chrono::{DateTime, NaiveTime, Utc};
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::helpers::{get_postgres_connection, PostgresConnection};
+use crate::types::{PostgresMaintenance, PostgresMaintenanceRun, PostgresMaintenanceWindow, PostgresSchema};
+
+const MAX_RUNS_IN_STATUS: usize = 10;
+
+pub async fn reconcile_postgres_maintenance(resource: Arc<PostgresMaintenance>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresMaintenance>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres maintenance {:?}", resource.metadata.name);
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("postgres maintenance {:?} is being deleted, skipping", resource.metadata.name);
+        return Ok(Action::await_change());
+    }
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let name = resource.name_any();
+
+    if resource.spec.schema.is_some() == resource.spec.tables.is_some() {
+        bail!("Exactly one of schema and tables has to be given for maintenance {}", name);
+    }
+
+    let schedule = parse_schedule(&resource.spec.schedule)?;
+    let window = resource.spec.window.as_ref().map(parse_window).transpose()?;
+
+    let now = Utc::now();
+    let status = resource.status.clone().unwrap_or_default();
+    let reference = status.last_scheduled_time.as_deref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|| resource.metadata.creation_timestamp.as_ref().map(|t| t.0))
+        .unwrap_or(now);
+
+    // Only the most recent of several missed runs is run
+    let due = schedule.after(&reference).take_while(|t| *t <= now).last();
+    let next = schedule.after(&now).next();
+
+    let mut runs = status.runs.unwrap_or_default();
+
+    if let Some(due) = due {
+        if window.as_ref().is_some_and(|w| !in_window(w, now)) {
+            info!("Skipping maintenance {} scheduled at {}, as it is outside the maintenance window", name, due);
+        } else {
+            info!("Running maintenance {} scheduled at {}", name, due);
+            let run = run_maintenance(&resource, &context, &namespace, window.as_ref()).await?;
+            if let Some(error) = &run.error {
+                error!("Maintenance {} failed: {}", name, error);
+            }
+            runs.insert(0, run);
+            runs.truncate(MAX_RUNS_IN_STATUS);
+        }
+    }
+
+    let status = json!({
+        "status": {
+            "lastScheduledTime": due.unwrap_or(reference).to_rfc3339(),
+            "nextRunTime": next.map(|t| t.to_rfc3339()),
+            "runs": runs,
+        }
+    });
+
+    let maintenance_api: Api<PostgresMaintenance> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    maintenance_api.patch_status(&name, &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    match next {
+        Some(next) => Ok(Action::requeue((next - Utc::now()).to_std().unwrap_or_default().max(Duration::from_secs(1)))),
+        None => Ok(Action::await_change()),
+    }
+}
+
+/// Parses a cron expression, which the cron crate only accepts with a seconds field.
+fn parse_schedule(schedule: &str) -> anyhow::Result<Schedule> {
+    let schedule = if schedule.split_whitespace().count() == 5 {
+        format!("0 {}", schedule)
+    } else {
+        schedule.to_string()
+    };
+
+    Schedule::from_str(&schedule).map_err(|e| anyhow!("Invalid schedule {}: {}", schedule, e))
+}
+
+fn parse_window(window: &PostgresMaintenanceWindow) -> anyhow::Result<(NaiveTime, NaiveTime)> {
+    let parse = |time: &str| NaiveTime::parse_from_str(time, "%H:%M").map_err(|e| anyhow!("Invalid maintenance window time {}: {}", time, e));
+    Ok((parse(&window.start)?, parse(&window.end)?))
+}
+
+fn in_window((start, end): &(NaiveTime, NaiveTime), now: DateTime<Utc>) -> bool {
+    let time = now.time();
+    if start <= end {
+        *start <= time && time < *end
+    } else {
+        *start <= time || time < *end
+    }
+}
+
+async fn run_maintenance(resource: &PostgresMaintenance, context: &ContextData, namespace: &str, window: Option<&(NaiveTime, NaiveTime)>) -> anyhow::Result<PostgresMaintenanceRun> {
+    let start_time = Utc::now();
+    let started = Instant::now();
+
+    let tables = get_tables(resource, context, namespace).await?;
+    let Some(tables) = tables else {
+        return Ok(PostgresMaintenanceRun {
+            start_time: start_time.to_rfc3339(),
+            duration_seconds: 0.0,
+            succeeded: false,
+            tables: 0,
+            error: Some("The schema to maintain was not found".to_string()),
+        });
+    };
+
+    let mut processed = 0;
+    let result: anyhow::Result<()> = async {
+        let pg_connection = get_postgres_connection(resource, context.kubernetes_client.clone()).await?;
+        if let Some(lock_timeout) = &resource.spec.lock_timeout {
+            pg_connection.execute("SELECT set_config('lock_timeout', $1, false)", &[lock_timeout]).await?;
+        }
+
+        for table in &tables {
+            if window.is_some_and(|w| !in_window(w, Utc::now())) {
+                bail!("The maintenance window closed after {} of {} tables", processed, tables.len());
+            }
+
+            maintain_table(&pg_connection, resource, table).await?;
+            processed += 1;
+        }
+
+        Ok(())
+    }.await;
+
+    Ok(PostgresMaintenanceRun {
+        start_time: start_time.to_rfc3339(),
+        duration_seconds: started.elapsed().as_secs_f64(),
+        succeeded: result.is_ok(),
+        tables: processed,
+        error: result.err().map(|e| format!("{:#}", e)),
+    })
+}
+
+/// VACUUM and REINDEX CONCURRENTLY cannot run in a transaction, so each statement is sent by itself.
+async fn maintain_table(pg_connection: &PostgresConnection, resource: &PostgresMaintenance, table: &str) -> anyhow::Result<()> {
+    debug!("Running {} on {}", resource.spec.operation, table);
+    pg_connection.batch_execute(&format!("{} {}", resource.spec.operation, table)).await
+        .map_err(|e| anyhow!("{} on {} failed: {}", resource.spec.operation, table, e))
+}
+
+/// Returns `None` when the referenced schema does not exist yet.
+async fn get_tables(resource: &PostgresMaintenance, context: &ContextData, namespace: &str) -> anyhow::Result<Option<Vec<String>>> {
+    match (&resource.spec.schema, &resource.spec.tables) {
+        (None, tables) => Ok(Some(tables.clone().unwrap_or_default())),
+        (Some(schema_reference), _) => {
+            let schema_api: Api<PostgresSchema> = Api::namespaced(context.kubernetes_client.clone(), schema_reference.namespace.as_deref().unwrap_or(namespace));
+            let Some(schema) = schema_api.get_opt(&schema_reference.name).await? else {
+                error!("Schema {} not found", schema_reference.name);
+                return Ok(None);
+            };
+
+            let pg_connection = get_postgres_connection(resource, context.kubernetes_client.clone()).await?;
+            let tables = pg_connection.query("SELECT format('%I.%I', schemaname, tablename) FROM pg_tables WHERE schemaname = $1 ORDER BY tablename", &[&schema.spec.schema]).await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+
+            Ok(Some(tables))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::{NaiveTime, TimeZone, Utc};
+
+    #[test]
+    fn test_schedule_and_window() {
+        let schedule = super::parse_schedule("30 2 * * *").unwrap();
+        let next = schedule.after(&Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()).next().unwrap();
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 2, 2, 30, 0).unwrap());
+
+        let window = (NaiveTime::from_hms_opt(23, 0, 0).unwrap(), NaiveTime::from_hms_opt(4, 0, 0).unwrap());
+        assert!(super::in_window(&window, next));
+        assert!(!super::in_window(&window, Utc.with_ymd_and_hms(2024, 1, 2, 12, 0, 0).unwrap()));
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use std::time::Duration;
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::get_postgres_connection;
+use crate::types::{PostgresPolicy, PostgresPolicyDefinition, PostgresSchema};
+
+pub async fn reconcile_postgres_policy(resource: Arc<PostgresPolicy>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresPolicy>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres policy {:?}", resource.metadata.name);
+
+    let table = &resource.spec.table;
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres policy {:?}", resource.metadata.name);
+
+        // Row level security is left enabled, so the table stays closed rather than becoming
+        // readable by everyone with access to it
+        if let Some(schema) = resource.status.as_ref().and_then(|s| s.schema_name.as_ref()) {
+            let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+
+            if pg_connection.query_opt("SELECT 1 FROM pg_tables WHERE schemaname = $1 AND tablename = $2", &[schema, table]).await?.is_some() {
+                for policy in &resource.spec.policies {
+                    pg_connection.execute(&format!("DROP POLICY IF EXISTS {} ON {}.{}", policy.name, schema, table), &[]).await?;
+                }
+            }
+        }
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+
+    let schema_reference = &resource.spec.schema;
+    let schema_api: Api<PostgresSchema> = Api::namespaced(context.kubernetes_client.clone(), schema_reference.namespace.as_ref().unwrap_or(&namespace));
+    let schema = if let Some(schema) = schema_api.get_opt(&schema_reference.name).await? {
+        schema.spec.schema
+    } else {
+        error!("Schema {} not found", schema_reference.name);
+        return Ok(Action::requeue(Duration::from_secs(30)));
+    };
+
+    let policy_api: Api<PostgresPolicy> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    let status = json!({
+        "status": {
+            "schemaName": schema,
+        }
+    });
+    policy_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    let qualified_table = format!("{}.{}", schema, table);
+
+    let mut pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+    let transaction = pg_connection.transaction().await?;
+
+    transaction.batch_execute(&format!("ALTER TABLE {} ENABLE ROW LEVEL SECURITY", qualified_table)).await?;
+    if resource.spec.force == Some(true) {
+        transaction.batch_execute(&format!("ALTER TABLE {} FORCE ROW LEVEL SECURITY", qualified_table)).await?;
+    } else {
+        transaction.batch_execute(&format!("ALTER TABLE {} NO FORCE ROW LEVEL SECURITY", qualified_table)).await?;
+    }
+
+    let existing_policies = transaction.query("
+        SELECT policyname::text, permissive, cmd, qual IS NOT NULL, with_check IS NOT NULL
+        FROM pg_policies
+        WHERE schemaname = $1 AND tablename = $2", &[&schema, table]).await?;
+
+    for existing in &existing_policies {
+        let name: &str = existing.get(0);
+        if !resource.spec.policies.iter().any(|p| p.name == name) {
+            info!("Dropping undeclared policy {} on {}", name, qualified_table);
+            transaction.batch_execute(&format!("DROP POLICY {} ON {}", name, qualified_table)).await?;
+        }
+    }
+
+    for policy in &resource.spec.policies {
+        let existing = existing_policies.iter().find(|e| e.get::<_, &str>(0) == policy.name);
+
+        let roles = policy.roles.as_ref()
+            .filter(|r| !r.is_empty())
+            .map(|r| r.join(", "))
+            .unwrap_or_else(|| "PUBLIC".to_string());
+        let kind = policy.kind.clone().unwrap_or_default().to_string();
+        let command = policy.command.clone().unwrap_or_default().to_string();
+
+        // ALTER POLICY can change everything but the kind and command, but cannot remove expressions
+        match existing {
+            Some(existing) if existing.get::<_, &str>(1) == kind
+                && existing.get::<_, &str>(2) == command
+                && existing.get::<_, bool>(3) == policy.using.is_some()
+                && existing.get::<_, bool>(4) == policy.with_check.is_some() => {
+                debug!("Altering policy {} on {}", policy.name, qualified_table);
+                transaction.batch_execute(&format!("ALTER POLICY {} ON {} TO {}{}", policy.name, qualified_table, roles, expressions(policy))).await?;
+            },
+            existing => {
+                if existing.is_some() {
+                    transaction.batch_execute(&format!("DROP POLICY {} ON {}", policy.name, qualified_table)).await?;
+                }
+
+                info!("Creating policy {} on {}", policy.name, qualified_table);
+                transaction.batch_execute(&format!("CREATE POLICY {} ON {} AS {} FOR {} TO {}{}", policy.name, qualified_table, kind, command, roles, expressions(policy))).await?;
+            },
+        }
+    }
+
+    transaction.commit().await?;
+
+    let status = json!({
+        "status": {
+            "ready": true,
+            "policies": resource.spec.policies.iter().map(|p| p.name.clone()).collect::<Vec<_>>(),
+        }
+    });
+    policy_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    info!("Postgres policies on {} reconciled", qualified_table);
+
+    Ok(Action::await_change())
+}
+
+fn expressions(policy: &PostgresPolicyDefinition) -> String {
+    let mut s = String::new();
+    if let Some(using) = &policy.using {
+        s.push_str(&format!(" USING ({})", using));
+    }
+    if let Some(with_check) = &policy.with_check {
+        s.push_str(&format!(" WITH CHECK ({})", with_check));
+    }
+    s
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use anyhow::bail;
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::get_postgres_connection;
+use crate::types::PostgresPublication;
+
+pub async fn reconcile_postgres_publication(resource: Arc<PostgresPublication>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresPublication>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres publication {:?}", resource.metadata.name);
+
+    let publication = &resource.spec.publication;
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres publication {:?}", resource.metadata.name);
+
+        let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+        pg_connection.execute(&format!("DROP PUBLICATION IF EXISTS {}", publication), &[]).await?;
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+    let all_tables = resource.spec.all_tables == Some(true);
+
+    let mut objects = vec![];
+    if let Some(tables) = resource.spec.tables.as_ref().filter(|t| !t.is_empty()) {
+        objects.push(format!("TABLE {}", tables.join(", ")));
+    }
+    if let Some(schemas) = resource.spec.schemas.as_ref().filter(|s| !s.is_empty()) {
+        objects.push(format!("TABLES IN SCHEMA {}", schemas.join(", ")));
+    }
+    let objects = objects.join(", ");
+
+    if all_tables && !objects.is_empty() {
+        bail!("Publication {} cannot publish all tables and specific tables or schemas at the same time", publication);
+    }
+
+    let publish = resource.spec.publish.as_ref()
+        .map(|operations| operations.iter().map(|o| o.to_string()).collect::<Vec<_>>().join(", "))
+        .unwrap_or_else(|| "insert, update, delete, truncate".to_string());
+
+    let pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+
+    let existing = pg_connection.query_opt("SELECT puballtables FROM pg_publication WHERE pubname = $1", &[&publication]).await?;
+
+    // FOR ALL TABLES cannot be altered, and publications cannot be altered to no longer contain any tables
+    let must_recreate = match &existing {
+        Some(row) => {
+            let existing_all_tables: bool = row.get(0);
+            let has_objects = if existing_all_tables {
+                true
+            } else {
+                let count: i64 = pg_connection.query_one("SELECT count(*) FROM pg_publication_tables WHERE pubname = $1", &[&publication]).await?.get(0);
+                count > 0
+            };
+
+            existing_all_tables != all_tables || (objects.is_empty() && has_objects)
+        }
+        None => false,
+    };
+
+    if must_recreate {
+        info!("Recreating publication {}", publication);
+        pg_connection.execute(&format!("DROP PUBLICATION {}", publication), &[]).await?;
+    }
+
+    if existing.is_none() || must_recreate {
+        let for_clause = if all_tables {
+            "FOR ALL TABLES".to_string()
+        } else if objects.is_empty() {
+            String::new()
+        } else {
+            format!("FOR {}", objects)
+        };
+
+        info!("Creating publication {}", publication);
+        pg_connection.execute(&format!("CREATE PUBLICATION {} {} WITH (publish = '{}')", publication, for_clause, publish), &[]).await?;
+    } else {
+        info!("Updating publication {}", publication);
+        if !all_tables && !objects.is_empty() {
+            pg_connection.execute(&format!("ALTER PUBLICATION {} SET {}", publication, objects), &[]).await?;
+        }
+        pg_connection.execute(&format!("ALTER PUBLICATION {} SET (publish = '{}')", publication, publish), &[]).await?;
+    }
+
+    let status = json!({
+        "status": {
+            "ready": true
+        }
+    });
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let publication_api: Api<PostgresPublication> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    publication_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    info!("Postgres publication {} reconciled", publication);
+
+    Ok(Action::await_change())
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::bail;
+use kube::{Api, Resource, ResourceExt};
+use kube_runtime::controller::Action;
+use kube_runtime::events::{Event, EventType, Recorder};
+use crate::{ContextData, Error};
+use crate::reconcilers::helpers::{get_postgres_connection, patch_status_if_changed};
+use crate::types::{PostgresQueryGuard, PostgresQueryGuardAction, PostgresQueryGuardStatus};
+
+const DEFAULT_INTERVAL_SECONDS: u64 = 60;
+
+pub async fn reconcile_postgres_query_guard(resource: Arc<PostgresQueryGuard>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresQueryGuard>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres query guard {:?}", resource.metadata.name);
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("postgres query guard {:?} is being deleted, skipping", resource.metadata.name);
+        return Ok(Action::await_change());
+    }
+
+    let spec = &resource.spec;
+    // An empty list guards everything, like a missing one, rather than nothing
+    let roles = spec.roles.as_ref().filter(|r| !r.is_empty());
+    let databases = spec.databases.as_ref().filter(|d| !d.is_empty());
+    if roles.is_none() && databases.is_none() {
+        bail!("Query guard {} has to guard at least one role or database", resource.name_any());
+    }
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let name = resource.name_any();
+    let action = spec.action.clone().unwrap_or_default();
+
+    let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+
+    let sessions = pg_connection.query("
+        SELECT pid, usename::text, datname::text, state,
+            extract(epoch FROM now() - query_start)::bigint,
+            extract(epoch FROM now() - state_change)::bigint,
+            left(query, 200)
+        FROM pg_stat_activity
+        WHERE backend_type = 'client backend'
+            AND pid <> pg_backend_pid()
+            AND ($1::text[] IS NULL OR usename = ANY($1))
+            AND ($2::text[] IS NULL OR datname = ANY($2))", &[&roles, &databases]).await?;
+
+    let recorder = Recorder::new(context.kubernetes_client.clone(), "postgres-topology-operator".into(), resource.object_ref(&()));
+
+    let mut cancelled = 0;
+    let mut terminated = 0;
+
+    for session in &sessions {
+        let pid: i32 = session.get(0);
+        let user: Option<String> = session.get(1);
+        let database: Option<String> = session.get(2);
+        let state: Option<String> = session.get(3);
+        let query_seconds: Option<i64> = session.get(4);
+        let state_seconds: Option<i64> = session.get(5);
+        let query: Option<String> = session.get(6);
+
+        let exceeds = |seconds: Option<i64>, max: Option<u64>| matches!((seconds, max), (Some(s), Some(m)) if s > m as i64);
+
+        let (action, reason, seconds) = match state.as_deref() {
+            Some("active") if exceeds(query_seconds, spec.max_query_duration_seconds) => (action.clone(), "LongRunningQuery", query_seconds),
+            Some("idle in transaction") | Some("idle in transaction (aborted)") if exceeds(state_seconds, spec.max_idle_in_transaction_seconds) => {
+                (PostgresQueryGuardAction::Terminate, "IdleInTransaction", state_seconds)
+            },
+            _ => continue,
+        };
+
+        let succeeded: bool = match action {
+            PostgresQueryGuardAction::Cancel => pg_connection.query_one("SELECT pg_cancel_backend($1)", &[&pid]).await?.get(0),
+            PostgresQueryGuardAction::Terminate => pg_connection.query_one("SELECT pg_terminate_backend($1)", &[&pid]).await?.get(0),
+        };
+
+        // The session ended by itself in the meantime
+        if !succeeded {
+            continue;
+        }
+
+        let note = format!(
+            "{} backend {} of {} on {} after {}s: {}",
+            action, pid, user.unwrap_or_default(), database.unwrap_or_default(), seconds.unwrap_or_default(), query.unwrap_or_default()
+        );
+        info!("Query guard {}: {}", name, note);
+
+        match action {
+            PostgresQueryGuardAction::Cancel => cancelled += 1,
+            PostgresQueryGuardAction::Terminate => terminated += 1,
+        }
+
+        let event = Event {
+            type_: EventType::Warning,
+            reason: reason.to_string(),
+            note: Some(note),
+            action: action.to_string(),
+            secondary: None,
+        };
+        if let Err(e) = recorder.publish(event).await {
+            warn!("Failed to publish event for query guard {}: {:?}", name, e);
+        }
+    }
+
+    let previous = resource.status.as_ref();
+    let status = PostgresQueryGuardStatus {
+        cancelled_queries: Some(previous.and_then(|s| s.cancelled_queries).unwrap_or(0) + cancelled),
+        terminated_sessions: Some(previous.and_then(|s| s.terminated_sessions).unwrap_or(0) + terminated),
+    };
+
+    let guard_api: Api<PostgresQueryGuard> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    patch_status_if_changed(&guard_api, &resource, status).await?;
+
+    Ok(Action::requeue(Duration::from_secs(spec.interval_seconds.unwrap_or(DEFAULT_INTERVAL_SECONDS))))
+}
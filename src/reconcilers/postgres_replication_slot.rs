@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::bail;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
+use kube::{Api, ResourceExt};
+use kube_runtime::controller::Action;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::{get_postgres_connection, patch_status_if_changed, with_condition};
+use crate::types::{PostgresReplicationSlot, PostgresReplicationSlotStatus, PostgresReplicationSlotType};
+
+const EXCESSIVE_WAL_RETENTION_CONDITION: &str = "ExcessiveWalRetention";
+
+pub async fn reconcile_postgres_replication_slot(resource: Arc<PostgresReplicationSlot>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresReplicationSlot>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres replication slot {:?}", resource.metadata.name);
+
+    let slot_name = &resource.spec.slot_name;
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres replication slot {:?}", resource.metadata.name);
+
+        // Fails while the slot is active, in which case the deletion is retried
+        let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+        pg_connection.execute("SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots WHERE slot_name = $1", &[&slot_name]).await?;
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+    let pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+
+    let (slot_type, plugin) = match resource.spec.slot_type {
+        PostgresReplicationSlotType::Physical => ("physical", None),
+        PostgresReplicationSlotType::Logical => ("logical", Some(resource.spec.plugin.clone().unwrap_or_else(|| "pgoutput".to_string()))),
+    };
+
+    // pg_current_wal_lsn cannot be used during recovery, where the slots of a standby are measured
+    // against the received WAL instead
+    let query = "
+        SELECT slot_type, plugin, active, restart_lsn::text, pg_wal_lsn_diff(
+            CASE WHEN pg_is_in_recovery() THEN pg_last_wal_receive_lsn() ELSE pg_current_wal_lsn() END,
+            restart_lsn)::bigint
+        FROM pg_replication_slots
+        WHERE slot_name = $1";
+
+    let slot = if let Some(slot) = pg_connection.query_opt(query, &[&slot_name]).await? {
+        slot
+    } else {
+        info!("Creating {} replication slot {}", slot_type, slot_name);
+        match &plugin {
+            None => pg_connection.execute("SELECT pg_create_physical_replication_slot($1, true)", &[&slot_name]).await?,
+            Some(plugin) => pg_connection.execute("SELECT pg_create_logical_replication_slot($1, $2)", &[&slot_name, plugin]).await?,
+        };
+        info!("Created replication slot {}", slot_name);
+
+        pg_connection.query_one(query, &[&slot_name]).await?
+    };
+
+    let existing_slot_type: &str = slot.get(0);
+    let existing_plugin: Option<String> = slot.get(1);
+    if existing_slot_type != slot_type || existing_plugin != plugin {
+        bail!("Replication slot {} already exists as a {} slot with plugin {:?}. Slots cannot be altered, drop it manually to recreate it", slot_name, existing_slot_type, existing_plugin);
+    }
+
+    let active: bool = slot.get(2);
+    let restart_lsn: Option<String> = slot.get(3);
+    let retained_wal_bytes: Option<i64> = slot.get(4);
+
+    let excessive_retention = match (resource.spec.max_inactive_retained_wal_bytes, retained_wal_bytes) {
+        (Some(max), Some(retained)) if !active && retained > max => {
+            warn!("Inactive replication slot {} retains {} bytes of WAL", slot_name, retained);
+            true
+        },
+        _ => false,
+    };
+
+    let message = match resource.spec.max_inactive_retained_wal_bytes {
+        Some(max) if excessive_retention => format!("Inactive slot retains more than {} bytes of WAL", max),
+        Some(max) => format!("Slot is active or retains at most {} bytes of WAL", max),
+        None => "No limit on the retained WAL".to_string(),
+    };
+
+    let status = PostgresReplicationSlotStatus {
+        active: Some(active),
+        restart_lsn,
+        retained_wal_bytes,
+        conditions: Some(with_condition(
+            resource.status.as_ref().and_then(|s| s.conditions.as_ref()),
+            EXCESSIVE_WAL_RETENTION_CONDITION,
+            excessive_retention,
+            if excessive_retention { "InactiveSlotRetainsWal" } else { "WithinThreshold" },
+            message,
+            resource.metadata.generation,
+        )),
+        last_progress_time: Some(Time(Utc::now())),
+    };
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let slot_api: Api<PostgresReplicationSlot> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    patch_status_if_changed(&slot_api, &resource, status).await?;
+
+    info!("Postgres replication slot {} reconciled", slot_name);
+
+    // Requeue to keep the WAL retention up to date
+    Ok(Action::requeue(Duration::from_secs(60)))
+}
@@ -4,13 +4,18 @@ use kube::{Api, Resource, ResourceExt};
 use kube::api::{Patch, PatchParams};
 use kube::core::object::HasStatus;
 use kube_runtime::controller::Action;
+use serde_json::json;
 use crate::{ContextData, Error};
 use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
-use crate::reconcilers::helpers::get_postgres_connection;
-use crate::types::{PgBouncerUser, PgBouncerUserSpec, PostgresRole, PostgresRoleStatus, StatusEncodedPassword};
+use crate::reconcilers::helpers::{get_postgres_connection, mark_not_ready};
+use crate::types::{DeletionPolicy, PgBouncerUser, PgBouncerUserSpec, PostgresRole, PostgresRoleStatus, StatusEncodedPassword};
 
 pub async fn reconcile_postgres_role(resource: Arc<PostgresRole>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
-    run_reconciler(resource, context).await.map_err(|e| e.into())
+    let result = run_reconciler(resource.clone(), context.clone()).await;
+    if result.is_err() && resource.status.as_ref().is_some_and(|s| s.ready) {
+        mark_not_ready(resource.as_ref(), context.kubernetes_client.clone()).await;
+    }
+    result.map_err(|e| e.into())
 }
 
 async fn run_reconciler(resource: Arc<PostgresRole>, context: Arc<ContextData>) -> anyhow::Result<Action> {
@@ -20,6 +25,12 @@ async fn run_reconciler(resource: Arc<PostgresRole>, context: Arc<ContextData>)
     if resource.metadata.deletion_timestamp.is_some() {
         info!("Deleting postgres role {:?}", resource.metadata.name);
 
+        if resource.spec.deletion_policy == Some(DeletionPolicy::Retain) {
+            info!("Retaining role {}", resource.spec.role);
+            remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+            return Ok(Action::await_change());
+        }
+
         let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
 
 
@@ -61,7 +72,9 @@ async fn run_reconciler(resource: Arc<PostgresRole>, context: Arc<ContextData>)
         status.encoded_password = Some(status_encoded_password)
     } else {
         *status = Some(PostgresRoleStatus {
-            encoded_password: Some(status_encoded_password)
+            encoded_password: Some(status_encoded_password),
+            ready: false,
+            search_path: None,
         })
     }
     resource.metadata.managed_fields = None;
@@ -91,10 +104,19 @@ async fn run_reconciler(resource: Arc<PostgresRole>, context: Arc<ContextData>)
     info!("Granting connect to {} to database {}", username, pg_connection.database);
     pg_connection.execute(&format!("GRANT CONNECT ON DATABASE {} TO {}", pg_connection.database, username), &[]).await?;
 
+    if let Some(search_path) = &resource.spec.search_path {
+        info!("Setting search_path of {} to {:?}", username, search_path);
+        pg_connection.execute(&format!("ALTER ROLE {} SET search_path TO {}", username, search_path.join(", ")), &[]).await?;
+    } else if resource.status.as_ref().is_some_and(|s| s.search_path.is_some()) {
+        info!("Resetting search_path of {}", username);
+        pg_connection.execute(&format!("ALTER ROLE {} RESET search_path", username), &[]).await?;
+    }
+
     info!("Postgres role {username} reconciled in database.");
 
 
-    if let Some(pg_bouncer_reference) = &resource.spec.register_in_pg_bouncer {
+    if let Some(registration) = &resource.spec.register_in_pg_bouncer {
+        let pg_bouncer_reference = &registration.pg_bouncer;
         info!("Registering role {username} in pg_bouncer {}", pg_bouncer_reference.name);
 
         let pg_bouncer_users_api: Api<PgBouncerUser> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
@@ -110,8 +132,13 @@ async fn run_reconciler(resource: Arc<PostgresRole>, context: Arc<ContextData>)
             },
             spec: PgBouncerUserSpec {
                 username: username.clone(),
-                password: resource.spec.password.with_new_text(password_text.clone()),
+                password: Some(resource.spec.password.with_new_text(password_text.clone())),
+                cert: None,
                 pg_bouncer: pg_bouncer_reference.clone(),
+                pool_mode: registration.pool_mode.clone(),
+                max_user_connections: registration.max_user_connections,
+                pool_size: registration.pool_size,
+                hba_rules: None,
             },
             status: None,
         };
@@ -121,6 +148,13 @@ async fn run_reconciler(resource: Arc<PostgresRole>, context: Arc<ContextData>)
         info!("Registered role {username} in pg_bouncer {}", pg_bouncer_reference.name);
     }
 
+    let status = json!({
+        "status": {
+            "ready": true,
+            "searchPath": resource.spec.search_path,
+        }
+    });
+    postgres_role_api.patch_status(&name, &PatchParams::default(), &Patch::Merge(status)).await?;
 
 
 
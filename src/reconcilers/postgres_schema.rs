@@ -1,26 +1,43 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
+use anyhow::bail;
+use k8s_openapi::api::core::v1::ConfigMap;
 use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
 use kube_runtime::controller::Action;
+use serde_json::json;
+use sha2::Digest;
 use crate::ContextData;
-use crate::types::{PostgresRole, PostgresSchema, PostgresSchemaOwner};
+use crate::types::{DeletionPolicy, PostgresRole, PostgresSchema, PostgresSchemaMigrations, PostgresSchemaOwner};
 use crate::Error;
 use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
-use crate::reconcilers::helpers::get_postgres_connection;
+use crate::reconcilers::helpers::{get_postgres_connection, mark_not_ready, PostgresConnection};
+use crate::reconcilers::schema_template::create_schema_from_template;
+
+const DEFAULT_MIGRATIONS_TRACKING_TABLE: &str = "__migrations";
 
 pub async fn reconcile_postgres_schema(resource: Arc<PostgresSchema>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
-    run_reconciler(resource, context).await.map_err(|e| e.into())
+    let result = run_reconciler(resource.clone(), context.clone()).await;
+    if result.is_err() && resource.status.as_ref().is_some_and(|s| s.ready) {
+        mark_not_ready(resource.as_ref(), context.kubernetes_client.clone()).await;
+    }
+    result.map_err(|e| e.into())
 }
 
 async fn run_reconciler(resource: Arc<PostgresSchema>, context: Arc<ContextData>) -> anyhow::Result<Action> {
     info!("Reconciling postgres_schema {:?}", resource.metadata.name);
 
     if resource.metadata.deletion_timestamp.is_some() {
-        info!("Deleting postgres role {:?}", resource.metadata.name);
+        info!("Deleting postgres schema {:?}", resource.metadata.name);
 
-        let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+        if resource.spec.deletion_policy == Some(DeletionPolicy::Retain) {
+            info!("Retaining schema {}", resource.spec.schema);
+        } else {
+            let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
 
-        pg_connection.execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", resource.spec.schema), &[]).await?;
+            pg_connection.execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", resource.spec.schema), &[]).await?;
+        }
 
         remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
 
@@ -49,15 +66,15 @@ async fn run_reconciler(resource: Arc<PostgresSchema>, context: Arc<ContextData>
         },
     };
 
-    let pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+    let mut pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
 
     let schema = &resource.spec.schema;
 
-    match (pg_connection.query_opt("SELECT schema_owner from information_schema.schemata where schema_name = $1", &[&schema]).await?, owner_name) {
-        (Some(_), None) => {
+    match (pg_connection.query_opt("SELECT schema_owner from information_schema.schemata where schema_name = $1", &[&schema]).await?, owner_name.as_deref(), resource.spec.from_template.as_deref()) {
+        (Some(_), None, _) => {
             info!("Schema {} already exists with specific owner", schema);
         },
-        (Some(schema_owner), Some(owner_name)) => {
+        (Some(schema_owner), Some(owner_name), _) => {
             let current_owner: &str = schema_owner.get(0);
             if current_owner != owner_name {
                 info!("Changing schema {} owner from {} to {}", schema, current_owner, owner_name);
@@ -67,18 +84,120 @@ async fn run_reconciler(resource: Arc<PostgresSchema>, context: Arc<ContextData>
                 info!("Schema {} already exists with owner {}", schema, owner_name);
             }
         },
-        (None, None) => {
+        (None, owner_name, Some(template)) => {
+            info!("Creating schema {} from template schema {}", schema, template);
+            create_schema_from_template(&mut pg_connection, template, schema, owner_name).await?;
+        },
+        (None, None, None) => {
             info!("Creating schema {} with specific owner", schema);
             pg_connection.execute(&format!("CREATE SCHEMA IF NOT EXISTS {}", schema), &[]).await?;
             info!("Schema created with specific owner");
         },
-        (None, Some(owner_name)) => {
+        (None, Some(owner_name), None) => {
             info!("Creating schema {} with owner {}", schema, owner_name);
             pg_connection.execute(&format!("CREATE SCHEMA IF NOT EXISTS {} AUTHORIZATION {}", schema, owner_name), &[]).await?;
             info!("Schema {} created with owner {}", schema, owner_name);
         }
+    };
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let schema_api: Api<PostgresSchema> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+
+    if let Some(migrations) = &resource.spec.migrations {
+        let config_map_api: Api<ConfigMap> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+
+        let scripts = if let Some(config_map) = config_map_api.get_opt(&migrations.config_map).await? {
+            config_map.data.unwrap_or_default()
+        } else {
+            error!("Migrations config map {} not found", migrations.config_map);
+            return Ok(Action::requeue(Duration::from_secs(30)));
+        };
+
+        let status = match apply_migrations(&mut pg_connection, schema, owner_name.as_deref(), migrations, &scripts).await {
+            Ok(version) => json!({
+                "status": {
+                    "ready": true,
+                    "currentMigrationVersion": version,
+                    "migrationError": null,
+                }
+            }),
+            Err(e) => {
+                let status = json!({
+                    "status": {
+                        "ready": false,
+                        "migrationError": format!("{:#}", e),
+                    }
+                });
+                schema_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+                return Err(e);
+            }
+        };
+
+        schema_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
+        // Changes to the config map do not trigger a reconcile, so check for new migrations periodically
+        return Ok(Action::requeue(Duration::from_secs(60)));
     }
 
+    let status = json!({
+        "status": {
+            "ready": true
+        }
+    });
+    schema_api.patch_status(&resource.name_any(), &PatchParams::default(), &Patch::Merge(status)).await?;
+
 
     Ok(Action::await_change())
+}
+
+/// Applies all migrations in `scripts` that have not been applied yet, each in its own transaction.
+/// Returns the highest applied version.
+async fn apply_migrations(pg_connection: &mut PostgresConnection, schema: &str, owner_name: Option<&str>, migrations: &PostgresSchemaMigrations, scripts: &BTreeMap<String, String>) -> anyhow::Result<Option<String>> {
+    let tracking_table = format!("{}.{}", schema, migrations.tracking_table.as_deref().unwrap_or(DEFAULT_MIGRATIONS_TRACKING_TABLE));
+
+    pg_connection.execute(&format!("CREATE TABLE IF NOT EXISTS {} (version text PRIMARY KEY, checksum text NOT NULL, applied_at timestamptz NOT NULL DEFAULT now())", tracking_table), &[]).await?;
+
+    let applied: BTreeMap<String, String> = pg_connection.query(&format!("SELECT version, checksum FROM {}", tracking_table), &[]).await?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    let highest_applied = applied.keys().next_back();
+
+    for (version, script) in scripts {
+        let checksum = base16ct::lower::encode_string(&sha2::Sha256::digest(script.as_bytes()));
+
+        if let Some(applied_checksum) = applied.get(version) {
+            if applied_checksum != &checksum {
+                bail!("Checksum of applied migration {} has changed from {} to {}. Refusing to apply further migrations to schema {}", version, applied_checksum, checksum, schema);
+            }
+            continue;
+        }
+
+        if let Some(highest_applied) = highest_applied.filter(|highest| *highest > version) {
+            warn!("Migration {} is ordered before the already applied migration {}", version, highest_applied);
+        }
+
+        info!("Applying migration {} to schema {}", version, schema);
+
+        let transaction = pg_connection.transaction().await?;
+        transaction.batch_execute(&format!("SET LOCAL search_path TO {}", schema)).await?;
+        if let Some(owner_name) = owner_name {
+            transaction.batch_execute(&format!("SET LOCAL ROLE {}", owner_name)).await?;
+        }
+        if let Err(e) = transaction.batch_execute(script).await {
+            bail!("Migration {} failed: {}", version, e);
+        }
+        transaction.batch_execute("RESET ROLE").await?;
+        transaction.execute(&format!("INSERT INTO {} (version, checksum) VALUES ($1, $2)", tracking_table), &[version, &checksum]).await?;
+        transaction.commit().await?;
+
+        info!("Applied migration {} to schema {}", version, schema);
+    }
+
+    let current_version = pg_connection.query_opt(&format!("SELECT max(version) FROM {}", tracking_table), &[]).await?
+        .and_then(|row| row.get(0));
+
+    Ok(current_version)
 }
\ No newline at end of file
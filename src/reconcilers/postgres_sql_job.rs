@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use std::time::Duration;
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use sha2::Digest;
+use tokio_postgres::SimpleQueryMessage;
+use crate::{ContextData, Error};
+use crate::reconcilers::helpers::{connect_to_postgres, get_postgres_admin_connection, PostgresConnection};
+use crate::types::{PostgresRole, PostgresSqlJob, PostgresSqlJobScript};
+
+const DEFAULT_BACKOFF_SECONDS: u64 = 30;
+
+pub async fn reconcile_postgres_sql_job(resource: Arc<PostgresSqlJob>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresSqlJob>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres sql job {:?}", resource.metadata.name);
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("postgres sql job {:?} is being deleted, skipping", resource.metadata.name);
+        return Ok(Action::await_change());
+    }
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let name = resource.name_any();
+
+    let script = match &resource.spec.script {
+        PostgresSqlJobScript::Inline(script) => script.clone(),
+        PostgresSqlJobScript::ConfigMap(key) => {
+            let config_map_api: Api<ConfigMap> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+            let script = config_map_api.get_opt(&key.name).await?
+                .and_then(|c| c.data)
+                .and_then(|mut d| d.remove(&key.key));
+
+            if let Some(script) = script {
+                script
+            } else {
+                error!("Key {} not found in config map {}", key.key, key.name);
+                return Ok(Action::requeue(Duration::from_secs(30)));
+            }
+        },
+    };
+
+    let role_name = if let Some(role_reference) = &resource.spec.run_as {
+        let role_api: Api<PostgresRole> = Api::namespaced(context.kubernetes_client.clone(), role_reference.namespace.as_ref().unwrap_or(&namespace));
+
+        if let Some(role) = role_api.get_opt(&role_reference.name).await? {
+            Some(role.spec.role)
+        } else {
+            error!("Role {} not found", role_reference.name);
+            return Ok(Action::requeue(Duration::from_secs(30)));
+        }
+    } else {
+        None
+    };
+
+    // Pointing the job at another server or database runs the script again
+    let admin_connection = get_postgres_admin_connection(&resource.spec.connection, &namespace, context.kubernetes_client.clone()).await?;
+
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(script.as_bytes());
+    hasher.update(role_name.as_deref().unwrap_or_default().as_bytes());
+    hasher.update(format!("{}:{}/{}", admin_connection.host, admin_connection.port, admin_connection.database).as_bytes());
+    let spec_hash = base16ct::lower::encode_string(&hasher.finalize());
+
+    // Changes to the config map do not trigger a reconcile
+    let idle_action = match &resource.spec.script {
+        PostgresSqlJobScript::Inline(_) => Action::await_change(),
+        PostgresSqlJobScript::ConfigMap(_) => Action::requeue(Duration::from_secs(300)),
+    };
+
+    let status = resource.status.as_ref().filter(|s| s.spec_hash.as_ref() == Some(&spec_hash));
+
+    let attempts = status.and_then(|s| s.attempts).unwrap_or(0);
+    if let Some(status) = status {
+        if status.succeeded == Some(true) {
+            debug!("Sql job {} has already succeeded", name);
+            return Ok(idle_action);
+        }
+
+        let max_retries = resource.spec.retry_policy.as_ref().map(|r| r.max_retries).unwrap_or(0);
+        if attempts > max_retries {
+            info!("Sql job {} has failed {} times, giving up", name, attempts);
+            return Ok(idle_action);
+        }
+
+        let backoff = resource.spec.retry_policy.as_ref().and_then(|r| r.backoff_seconds).unwrap_or(DEFAULT_BACKOFF_SECONDS);
+        let last_run_time = status.last_run_time.as_deref().and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+        if let Some(last_run_time) = last_run_time {
+            let elapsed = (Utc::now() - last_run_time.with_timezone(&Utc)).num_seconds().max(0) as u64;
+            if elapsed < backoff {
+                return Ok(Action::requeue(Duration::from_secs(backoff - elapsed)));
+            }
+        }
+    }
+
+    info!("Running sql job {} (attempt {})", name, attempts + 1);
+
+    let mut pg_connection = connect_to_postgres(&admin_connection).await?;
+    let result = run_script(&mut pg_connection, &script, role_name.as_deref()).await;
+
+    let (succeeded, rows_affected, error) = match result {
+        Ok(rows_affected) => {
+            info!("Sql job {} succeeded, affecting {} rows", name, rows_affected);
+            (true, Some(rows_affected), None)
+        },
+        Err(e) => {
+            error!("Sql job {} failed: {:?}", name, e);
+            (false, None, Some(format!("{:#}", e)))
+        },
+    };
+
+    let status = json!({
+        "status": {
+            "specHash": spec_hash,
+            "succeeded": succeeded,
+            "attempts": attempts + 1,
+            "error": error,
+            "rowsAffected": rows_affected,
+            "lastRunTime": Utc::now().to_rfc3339(),
+        }
+    });
+
+    let sql_job_api: Api<PostgresSqlJob> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    sql_job_api.patch_status(&name, &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    // A failed attempt is retried through the backoff check above
+    if succeeded {
+        Ok(idle_action)
+    } else {
+        Ok(Action::requeue(Duration::from_secs(1)))
+    }
+}
+
+/// Runs the script in a transaction, and returns the total number of rows affected by its statements.
+async fn run_script(pg_connection: &mut PostgresConnection, script: &str, role_name: Option<&str>) -> anyhow::Result<u64> {
+    let transaction = pg_connection.transaction().await?;
+
+    if let Some(role_name) = role_name {
+        transaction.batch_execute(&format!("SET LOCAL ROLE {}", role_name)).await?;
+    }
+
+    let rows_affected = transaction.simple_query(script).await?
+        .iter()
+        .map(|message| match message {
+            SimpleQueryMessage::CommandComplete(rows) => *rows,
+            _ => 0,
+        })
+        .sum();
+
+    transaction.commit().await?;
+
+    Ok(rows_affected)
+}
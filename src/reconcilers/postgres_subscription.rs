@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use std::time::Duration;
+use itertools::Itertools;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
+use kube::{Api, ResourceExt};
+use kube_runtime::controller::Action;
+use sha2::Digest;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::{connect_to_postgres, get_postgres_admin_connection, get_postgres_connection, patch_status_if_changed, quote_literal, to_connection_string};
+use crate::types::{PostgresAdminConnectionSpec, PostgresSubscription, PostgresSubscriptionStatus};
+
+pub async fn reconcile_postgres_subscription(resource: Arc<PostgresSubscription>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresSubscription>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres subscription {:?}", resource.metadata.name);
+
+    let subscription = &resource.spec.subscription;
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres subscription {:?}", resource.metadata.name);
+
+        // Dropping the subscription also drops the replication slot on the publisher
+        let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+        pg_connection.batch_execute(&format!("DROP SUBSCRIPTION IF EXISTS {}", subscription)).await?;
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+
+    let source = get_postgres_admin_connection(&resource.spec.source, &namespace, context.kubernetes_client.clone()).await?;
+    let connection_string = to_connection_string(&source);
+    let connection_hash = base16ct::lower::encode_string(&sha2::Sha256::digest(connection_string.as_bytes()));
+
+    let enabled = resource.spec.enabled.unwrap_or(true);
+    let publications = resource.spec.publications.iter().sorted().join(", ");
+
+    let pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+
+    let existing = pg_connection.query_opt("
+        SELECT s.subenabled, s.subpublications::text[]
+        FROM pg_subscription s
+            JOIN pg_database d ON d.oid = s.subdbid
+        WHERE s.subname = $1 AND d.datname = current_database()", &[&subscription]).await?;
+
+    // Subscription commands cannot run inside a transaction block, so they are sent as simple queries
+    if let Some(existing) = existing {
+        let existing_enabled: bool = existing.get(0);
+        let existing_publications: Vec<String> = existing.get(1);
+
+        if resource.status.as_ref().and_then(|s| s.connection_hash.as_ref()) != Some(&connection_hash) {
+            info!("Updating connection of subscription {}", subscription);
+            pg_connection.batch_execute(&format!("ALTER SUBSCRIPTION {} CONNECTION {}", subscription, quote_literal(&connection_string))).await?;
+        }
+
+        if existing_enabled != enabled {
+            info!("{} subscription {}", if enabled { "Enabling" } else { "Disabling" }, subscription);
+            pg_connection.batch_execute(&format!("ALTER SUBSCRIPTION {} {}", subscription, if enabled { "ENABLE" } else { "DISABLE" })).await?;
+        }
+
+        // Disabled subscriptions cannot refresh the tables of their publications
+        if existing_publications.iter().sorted().join(", ") != publications {
+            info!("Updating publications of subscription {} to {}", subscription, publications);
+            pg_connection.batch_execute(&format!("ALTER SUBSCRIPTION {} SET PUBLICATION {} WITH (refresh = {})", subscription, publications, enabled)).await?;
+        }
+    } else {
+        info!("Creating subscription {}", subscription);
+        pg_connection.batch_execute(&format!(
+            "CREATE SUBSCRIPTION {} CONNECTION {} PUBLICATION {} WITH (slot_name = {}, create_slot = {}, copy_data = {}, enabled = {})",
+            subscription,
+            quote_literal(&connection_string),
+            publications,
+            quote_literal(resource.spec.slot_name.as_ref().unwrap_or(subscription)),
+            resource.spec.create_slot.unwrap_or(true),
+            resource.spec.copy_data.unwrap_or(true),
+            enabled,
+        )).await?;
+        info!("Created subscription {}", subscription);
+    }
+
+    // The row without a relid is the main apply worker, the others are table synchronization workers
+    let stats = pg_connection.query_opt("
+        SELECT pid IS NOT NULL, received_lsn::text, latest_end_lsn::text, last_msg_receipt_time::text, latest_end_time::text
+        FROM pg_stat_subscription
+        WHERE subname = $1 AND relid IS NULL", &[&subscription]).await?;
+
+    let (active, received_lsn, latest_end_lsn, last_message_receipt_time, latest_end_time): (bool, Option<String>, Option<String>, Option<String>, Option<String>) = match &stats {
+        Some(row) => (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4)),
+        None => (false, None, None, None, None),
+    };
+
+    let lag_bytes = match &received_lsn {
+        Some(received_lsn) => get_lag_bytes(&source, received_lsn).await,
+        None => None,
+    };
+
+    let status = PostgresSubscriptionStatus {
+        ready: true,
+        connection_hash: Some(connection_hash),
+        enabled: Some(enabled),
+        active: Some(active),
+        received_lsn,
+        latest_end_lsn,
+        last_message_receipt_time,
+        latest_end_time,
+        lag_bytes,
+        last_progress_time: Some(Time(Utc::now())),
+    };
+
+    let subscription_api: Api<PostgresSubscription> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    patch_status_if_changed(&subscription_api, &resource, status).await?;
+
+    info!("Postgres subscription {} reconciled", subscription);
+
+    // Requeue to keep the replication statistics up to date
+    Ok(Action::requeue(Duration::from_secs(30)))
+}
+
+async fn get_lag_bytes(source: &PostgresAdminConnectionSpec, received_lsn: &str) -> Option<i64> {
+    let result = async {
+        let publisher = connect_to_postgres(source).await?;
+        let row = publisher.query_one("SELECT pg_wal_lsn_diff(pg_current_wal_lsn(), $1::text::pg_lsn)::bigint", &[&received_lsn]).await?;
+        anyhow::Ok(row.get(0))
+    }.await;
+
+    match result {
+        Ok(lag_bytes) => Some(lag_bytes),
+        Err(e) => {
+            warn!("Could not determine replication lag from publisher: {:?}", e);
+            None
+        }
+    }
+}
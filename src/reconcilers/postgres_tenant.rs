@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use anyhow::bail;
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{Api, Resource, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::helpers::get_postgres_admin_connection;
+use crate::reconcilers::pg_bouncer::PG_BOUNCER_PORT;
+use crate::types::{PgBouncer, PgBouncerUser, PostgresRole, PostgresRoleReference, PostgresRoleSpec, PostgresSchema, PostgresSchemaOwner, PostgresSchemaSpec, PostgresTenant};
+
+pub async fn reconcile_postgres_tenant(resource: Arc<PostgresTenant>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresTenant>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres tenant {:?}", resource.metadata.name);
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        // The children are owned by the tenant, and are deleted by kubernetes according to their
+        // deletion policy
+        info!("postgres tenant {:?} is being deleted, skipping", resource.metadata.name);
+        return Ok(Action::await_change());
+    }
+
+    // The connection secret needs the password the tenant logs in with
+    let Some(password) = resource.spec.password.get_plaintext() else {
+        bail!("The password of tenant {} must be given in plaintext, as it is written to the connection secret", resource.name_any());
+    };
+
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+    let name = resource.name_any();
+    let owner_reference = resource.controller_owner_ref(&()).unwrap();
+    let serverside = PatchParams::apply("postgres-topology-operator").force();
+
+    let schema = resource.spec.schema.clone().unwrap_or_else(|| resource.spec.role.clone());
+
+    let role = PostgresRole {
+        metadata: ObjectMeta {
+            namespace: Some(namespace.clone()),
+            name: Some(name.clone()),
+            owner_references: Some(vec![owner_reference.clone()]),
+            ..Default::default()
+        },
+        spec: PostgresRoleSpec {
+            role: resource.spec.role.clone(),
+            password: resource.spec.password.clone(),
+            register_in_pg_bouncer: resource.spec.register_in_pg_bouncer.clone(),
+            grant_role_to_admin_user: resource.spec.grant_role_to_admin_user,
+            search_path: Some(vec![schema.clone()]),
+            deletion_policy: resource.spec.deletion_policy,
+            connection: resource.spec.connection.clone(),
+        },
+        status: None,
+    };
+
+    let role_api: Api<PostgresRole> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    let role = role_api.patch(&name, &serverside, &Patch::Apply(role)).await?;
+    info!("Applied role {} for tenant {}", resource.spec.role, name);
+
+    let postgres_schema = PostgresSchema {
+        metadata: ObjectMeta {
+            namespace: Some(namespace.clone()),
+            name: Some(name.clone()),
+            owner_references: Some(vec![owner_reference.clone()]),
+            ..Default::default()
+        },
+        spec: PostgresSchemaSpec {
+            schema: schema.clone(),
+            schema_owner: Some(PostgresSchemaOwner::ManagedRole(PostgresRoleReference {
+                name: name.clone(),
+                namespace: None,
+            })),
+            connection: resource.spec.connection.clone(),
+            from_template: resource.spec.from_template.clone(),
+            migrations: None,
+            deletion_policy: resource.spec.deletion_policy,
+        },
+        status: None,
+    };
+
+    let schema_api: Api<PostgresSchema> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    let postgres_schema = schema_api.patch(&name, &serverside, &Patch::Apply(postgres_schema)).await?;
+    info!("Applied schema {} for tenant {}", schema, name);
+
+    let admin_connection = get_postgres_admin_connection(&resource.spec.connection, &namespace, context.kubernetes_client.clone()).await?;
+
+    let (host, port) = if let Some(pg_bouncer_reference) = resource.spec.register_in_pg_bouncer.as_ref().map(|r| &r.pg_bouncer) {
+        let pg_bouncer_namespace = pg_bouncer_reference.namespace.as_ref().unwrap_or(&namespace);
+        let pg_bouncer_api: Api<PgBouncer> = Api::namespaced(context.kubernetes_client.clone(), pg_bouncer_namespace);
+        let pg_bouncer = pg_bouncer_api.get(&pg_bouncer_reference.name).await?;
+
+        (
+            format!("{}.{}.svc", pg_bouncer.spec.service.name, pg_bouncer_namespace),
+            pg_bouncer.spec.service.port.unwrap_or(PG_BOUNCER_PORT).to_string(),
+        )
+    } else {
+        (admin_connection.host.clone(), admin_connection.port.to_string())
+    };
+
+    let secret_name = resource.spec.connection_secret_name.clone().unwrap_or_else(|| format!("{}-connection", name));
+
+    let secret = Secret {
+        metadata: ObjectMeta {
+            namespace: Some(namespace.clone()),
+            name: Some(secret_name.clone()),
+            owner_references: Some(vec![owner_reference.clone()]),
+            labels: Some(BTreeMap::from([("controller-watcher".to_string(), "postgres-topology-operator".to_string())])),
+            ..Default::default()
+        },
+        string_data: Some(BTreeMap::from([
+            ("host".to_string(), host),
+            ("port".to_string(), port),
+            ("database".to_string(), admin_connection.database.clone()),
+            ("schema".to_string(), schema.clone()),
+            ("username".to_string(), resource.spec.role.clone()),
+            ("password".to_string(), password.to_string()),
+        ])),
+        ..Default::default()
+    };
+
+    let secret_api: Api<Secret> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    secret_api.patch(&secret_name, &serverside, &Patch::Apply(secret)).await?;
+    info!("Applied connection secret {} for tenant {}", secret_name, name);
+
+    let mut not_ready = vec![];
+    if !role.status.as_ref().is_some_and(|s| s.ready) {
+        not_ready.push("PostgresRole".to_string());
+    }
+    if !postgres_schema.status.as_ref().is_some_and(|s| s.ready) {
+        not_ready.push("PostgresSchema".to_string());
+    }
+    if resource.spec.register_in_pg_bouncer.is_some() {
+        // The user is registered in pg bouncer by the role reconciler
+        let pg_bouncer_user_api: Api<PgBouncerUser> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+        if pg_bouncer_user_api.get_opt(&name).await?.is_none() {
+            not_ready.push("PgBouncerUser".to_string());
+        }
+    }
+
+    let status = json!({
+        "status": {
+            "ready": not_ready.is_empty(),
+            "notReady": not_ready,
+        }
+    });
+
+    let tenant_api: Api<PostgresTenant> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    tenant_api.patch_status(&name, &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    info!("Postgres tenant {} reconciled", name);
+
+    Ok(Action::await_change())
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use kube_runtime::controller::Action;
+use serde_json::json;
+use crate::{ContextData, Error};
+use crate::reconcilers::finalizers::{ensure_finalizer, remove_finalizer};
+use crate::reconcilers::helpers::{get_postgres_connection, quote_literal, PostgresConnection};
+use crate::reconcilers::postgres_foreign_server::alter_options_clauses;
+use crate::types::{PostgresForeignServer, PostgresImportForeignSchema, PostgresRole, PostgresSchema, PostgresUserMapping};
+
+pub async fn reconcile_postgres_user_mapping(resource: Arc<PostgresUserMapping>, context: Arc<ContextData>) -> anyhow::Result<Action, Error> {
+    run_reconciler(resource, context).await.map_err(|e| e.into())
+}
+
+async fn run_reconciler(resource: Arc<PostgresUserMapping>, context: Arc<ContextData>) -> anyhow::Result<Action> {
+    info!("Reconciling postgres user mapping {:?}", resource.metadata.name);
+
+    if resource.metadata.deletion_timestamp.is_some() {
+        info!("Deleting postgres user mapping {:?}", resource.metadata.name);
+
+        let status = resource.status.as_ref();
+        if let (Some(server_name), Some(role_name)) = (status.and_then(|s| s.server_name.as_ref()), status.and_then(|s| s.role_name.as_ref())) {
+            let pg_connection = get_postgres_connection(resource.as_ref(), context.kubernetes_client.clone()).await?;
+            pg_connection.execute(&format!("DROP USER MAPPING IF EXISTS FOR {} SERVER {}", role_name, server_name), &[]).await?;
+        } else {
+            info!("User mapping {:?} was never created", resource.metadata.name);
+        }
+
+        remove_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+
+        return Ok(Action::await_change());
+    }
+
+    let resource = ensure_finalizer(resource.as_ref().clone(), context.kubernetes_client.clone()).await?;
+    let namespace = resource.namespace().expect("Resource should be namespaced");
+
+    let server_reference = &resource.spec.server;
+    let server_api: Api<PostgresForeignServer> = Api::namespaced(context.kubernetes_client.clone(), server_reference.namespace.as_ref().unwrap_or(&namespace));
+    let server_name = if let Some(server) = server_api.get_opt(&server_reference.name).await? {
+        server.spec.server_name
+    } else {
+        error!("Foreign server {} not found", server_reference.name);
+        return Ok(Action::requeue(Duration::from_secs(30)));
+    };
+
+    let role_reference = &resource.spec.role;
+    let role_api: Api<PostgresRole> = Api::namespaced(context.kubernetes_client.clone(), role_reference.namespace.as_ref().unwrap_or(&namespace));
+    let role_name = if let Some(role) = role_api.get_opt(&role_reference.name).await? {
+        role.spec.role
+    } else {
+        error!("Role {} not found", role_reference.name);
+        return Ok(Action::requeue(Duration::from_secs(30)));
+    };
+
+    let credentials = &resource.spec.credentials;
+    let secret_api: Api<Secret> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    let secret = secret_api.get(&credentials.secret_name).await?;
+    let get_secret_value = |key: &str| -> anyhow::Result<String> {
+        let value = secret.data.as_ref()
+            .and_then(|d| d.get(key))
+            .ok_or_else(|| anyhow!("Secret {} does not contain the key {}", credentials.secret_name, key))?;
+        Ok(String::from_utf8(value.0.clone())?)
+    };
+
+    let options = BTreeMap::from([
+        ("user".to_string(), get_secret_value(credentials.username_key.as_deref().unwrap_or("username"))?),
+        ("password".to_string(), get_secret_value(credentials.password_key.as_deref().unwrap_or("password"))?),
+    ]);
+
+    let mut pg_connection = get_postgres_connection(&resource, context.kubernetes_client.clone()).await?;
+
+    if let Some(existing) = pg_connection.query_opt("SELECT umoptions FROM pg_user_mappings WHERE srvname = $1 AND usename = $2", &[&server_name, &role_name]).await? {
+        let existing: Option<Vec<String>> = existing.get(0);
+        let clauses = alter_options_clauses(&existing.unwrap_or_default(), &options);
+
+        if !clauses.is_empty() {
+            info!("Updating user mapping for {} on server {}", role_name, server_name);
+            pg_connection.execute(&format!("ALTER USER MAPPING FOR {} SERVER {} OPTIONS ({})", role_name, server_name, clauses.join(", ")), &[]).await?;
+        }
+    } else {
+        info!("Creating user mapping for {} on server {}", role_name, server_name);
+        pg_connection.execute(&format!(
+            "CREATE USER MAPPING FOR {} SERVER {} OPTIONS (user {}, password {})",
+            role_name, server_name, quote_literal(&options["user"]), quote_literal(&options["password"])
+        ), &[]).await?;
+    }
+
+    pg_connection.execute(&format!("GRANT USAGE ON FOREIGN SERVER {} TO {}", server_name, role_name), &[]).await?;
+
+    let resource_name = resource.name_any();
+    let mapping_api: Api<PostgresUserMapping> = Api::namespaced(context.kubernetes_client.clone(), &namespace);
+    let status = json!({
+        "status": {
+            "serverName": server_name,
+            "roleName": role_name,
+        }
+    });
+    mapping_api.patch_status(&resource_name, &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    if let Some(import) = &resource.spec.import_foreign_schema {
+        let schema_api: Api<PostgresSchema> = Api::namespaced(context.kubernetes_client.clone(), import.into.namespace.as_ref().unwrap_or(&namespace));
+        let local_schema = if let Some(schema) = schema_api.get_opt(&import.into.name).await? {
+            schema.spec.schema
+        } else {
+            error!("Schema {} not found", import.into.name);
+            return Ok(Action::requeue(Duration::from_secs(30)));
+        };
+
+        import_foreign_schema(&mut pg_connection, import, &server_name, &role_name, &local_schema).await?;
+    }
+
+    let status = json!({
+        "status": {
+            "ready": true
+        }
+    });
+    mapping_api.patch_status(&resource_name, &PatchParams::default(), &Patch::Merge(status)).await?;
+
+    info!("Postgres user mapping for {} on server {} reconciled", role_name, server_name);
+
+    // Changes to the credentials secret and the remote schema do not trigger a reconcile
+    Ok(Action::requeue(Duration::from_secs(300)))
+}
+
+async fn import_foreign_schema(pg_connection: &mut PostgresConnection, import: &PostgresImportForeignSchema, server_name: &str, role_name: &str, local_schema: &str) -> anyhow::Result<()> {
+    let imported: Vec<String> = pg_connection.query("
+        SELECT c.relname
+        FROM pg_foreign_table ft
+            JOIN pg_class c ON c.oid = ft.ftrelid
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            JOIN pg_foreign_server s ON s.oid = ft.ftserver
+        WHERE n.nspname = $1 AND s.srvname = $2", &[&local_schema, &server_name]).await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let filter = match (&import.limit_to, &import.except) {
+        (Some(_), Some(_)) => bail!("Only one of limitTo and except can be used when importing a foreign schema"),
+        (Some(limit_to), None) => {
+            let missing: Vec<&String> = limit_to.iter().filter(|t| !imported.contains(t)).collect();
+            if missing.is_empty() {
+                debug!("All tables of {} are already imported into {}", import.remote_schema, local_schema);
+                return Ok(());
+            }
+            format!("LIMIT TO ({})", missing.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", "))
+        },
+        (None, except) => {
+            let except: Vec<&String> = except.iter().flatten().chain(imported.iter()).collect();
+            if except.is_empty() {
+                String::new()
+            } else {
+                format!("EXCEPT ({})", except.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", "))
+            }
+        },
+    };
+
+    info!("Importing foreign schema {} from server {} into {}", import.remote_schema, server_name, local_schema);
+
+    // Imported as the role, so its user mapping is used and it owns the foreign tables
+    let transaction = pg_connection.transaction().await?;
+    transaction.batch_execute(&format!("SET LOCAL ROLE {}", role_name)).await?;
+    transaction.batch_execute(&format!("IMPORT FOREIGN SCHEMA {} {} FROM SERVER {} INTO {}", import.remote_schema, filter, server_name, local_schema)).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
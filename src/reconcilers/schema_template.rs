@@ -0,0 +1,230 @@
+use anyhow::bail;
+use tokio_postgres::Transaction;
+use crate::reconcilers::helpers::PostgresConnection;
+
+/// Creates the schema, and copies the sequences, tables (including their indexes and constraints),
+/// functions and views of the template schema into it, in one transaction so a failed copy does
+/// not leave an empty schema behind.
+///
+/// Definitions are rendered with the template schema on the search path and created with the new
+/// schema on the search path, so references between objects inside the template end up pointing
+/// at their copies rather than at the template. References in function bodies qualified with the
+/// template schema are rewritten textually, which also rewrites them inside string literals.
+pub async fn create_schema_from_template(pg_connection: &mut PostgresConnection, template: &str, schema: &str, owner_name: Option<&str>) -> anyhow::Result<()> {
+    if pg_connection.query_opt("SELECT 1 FROM pg_namespace WHERE nspname = $1", &[&template]).await?.is_none() {
+        bail!("Template schema {} does not exist", template);
+    }
+
+    let transaction = pg_connection.transaction().await?;
+    match owner_name {
+        Some(owner_name) => transaction.batch_execute(&format!("CREATE SCHEMA {} AUTHORIZATION {}", schema, owner_name)).await?,
+        None => transaction.batch_execute(&format!("CREATE SCHEMA {}", schema)).await?,
+    }
+    transaction.batch_execute("SET LOCAL check_function_bodies = false").await?;
+
+    let sequences = transaction.query("
+        SELECT quote_ident(c.relname), s.seqtypid::regtype::text, s.seqstart, s.seqincrement, s.seqmin, s.seqmax, s.seqcache, s.seqcycle
+        FROM pg_sequence s
+            JOIN pg_class c ON c.oid = s.seqrelid
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1
+            AND NOT EXISTS (SELECT 1 FROM pg_depend d WHERE d.objid = c.oid AND d.deptype = 'i')
+        ORDER BY c.relname", &[&template]).await?;
+
+    for sequence in sequences {
+        let name: &str = sequence.get(0);
+        let data_type: &str = sequence.get(1);
+        let start: i64 = sequence.get(2);
+        let increment: i64 = sequence.get(3);
+        let min: i64 = sequence.get(4);
+        let max: i64 = sequence.get(5);
+        let cache: i64 = sequence.get(6);
+        let cycle: bool = sequence.get(7);
+
+        debug!("Copying sequence {} from template {}", name, template);
+        transaction.batch_execute(&format!(
+            "CREATE SEQUENCE {}.{} AS {} INCREMENT BY {} MINVALUE {} MAXVALUE {} START WITH {} CACHE {} {}",
+            schema, name, data_type, increment, min, max, start, cache, if cycle { "CYCLE" } else { "NO CYCLE" }
+        )).await?;
+    }
+
+    let tables = query_names(&transaction, "
+        SELECT quote_ident(c.relname)
+        FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relkind IN ('r', 'p') AND NOT c.relispartition
+        ORDER BY c.relname", template).await?;
+
+    for table in &tables {
+        debug!("Copying table {} from template {}", table, template);
+        transaction.batch_execute(&format!("CREATE TABLE {}.{} (LIKE {}.{} INCLUDING ALL)", schema, table, template, table)).await?;
+    }
+
+    transaction.batch_execute(&format!("SET LOCAL search_path TO {}", template)).await?;
+
+    let functions = query_names(&transaction, "
+        SELECT pg_get_functiondef(p.oid)
+        FROM pg_proc p
+            JOIN pg_namespace n ON n.oid = p.pronamespace
+        WHERE n.nspname = $1 AND p.prokind IN ('f', 'p')
+        ORDER BY p.oid", template).await?;
+
+    // The copied tables still have defaults pointing at the sequences and functions of the template.
+    // Rendered relative to the template they become unqualified, and are rebound when set again below.
+    let column_defaults = transaction.query("
+        SELECT quote_ident(c.relname), quote_ident(a.attname), pg_get_expr(d.adbin, d.adrelid)
+        FROM pg_attrdef d
+            JOIN pg_class c ON c.oid = d.adrelid
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            JOIN pg_attribute a ON a.attrelid = d.adrelid AND a.attnum = d.adnum
+        WHERE n.nspname = $1 AND a.attgenerated = ''", &[&schema]).await?;
+
+    let sequence_owners = transaction.query("
+        SELECT quote_ident(s.relname), quote_ident(t.relname), quote_ident(a.attname)
+        FROM pg_depend d
+            JOIN pg_class s ON s.oid = d.objid
+            JOIN pg_class t ON t.oid = d.refobjid
+            JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = d.refobjsubid
+            JOIN pg_namespace n ON n.oid = s.relnamespace
+        WHERE n.nspname = $1 AND s.relkind = 'S' AND d.deptype = 'a'", &[&template]).await?;
+
+    let foreign_keys = transaction.query("
+        SELECT quote_ident(c.relname), quote_ident(con.conname), pg_get_constraintdef(con.oid)
+        FROM pg_constraint con
+            JOIN pg_class c ON c.oid = con.conrelid
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND con.contype = 'f'
+        ORDER BY con.oid", &[&template]).await?;
+
+    let views = transaction.query("
+        SELECT quote_ident(c.relname), c.relkind = 'm', pg_get_viewdef(c.oid)
+        FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relkind IN ('v', 'm')
+        ORDER BY c.oid", &[&template]).await?;
+
+    transaction.batch_execute(&format!("SET LOCAL search_path TO {}", schema)).await?;
+
+    for function in &functions {
+        // pg_get_functiondef always qualifies the name of the function itself, and bodies may
+        // qualify references to other objects of the template
+        transaction.batch_execute(&rewrite_schema_references(function, template, schema)).await?;
+    }
+
+    for column_default in column_defaults {
+        let table: &str = column_default.get(0);
+        let column: &str = column_default.get(1);
+        let expression: &str = column_default.get(2);
+        transaction.batch_execute(&format!("ALTER TABLE {}.{} ALTER COLUMN {} SET DEFAULT {}", schema, table, column, expression)).await?;
+    }
+
+    for sequence_owner in sequence_owners {
+        let sequence: &str = sequence_owner.get(0);
+        let table: &str = sequence_owner.get(1);
+        let column: &str = sequence_owner.get(2);
+        transaction.batch_execute(&format!("ALTER SEQUENCE {}.{} OWNED BY {}.{}.{}", schema, sequence, schema, table, column)).await?;
+    }
+
+    for foreign_key in foreign_keys {
+        let table: &str = foreign_key.get(0);
+        let name: &str = foreign_key.get(1);
+        let definition: &str = foreign_key.get(2);
+        transaction.batch_execute(&format!("ALTER TABLE {}.{} ADD CONSTRAINT {} {}", schema, table, name, definition)).await?;
+    }
+
+    for view in views {
+        let name: &str = view.get(0);
+        let materialized: bool = view.get(1);
+        let definition: &str = view.get(2);
+        let kind = if materialized { "MATERIALIZED VIEW" } else { "VIEW" };
+        transaction.batch_execute(&format!("CREATE {} {}.{} AS {}", kind, schema, name, definition.trim_end_matches(';'))).await?;
+    }
+
+    if let Some(owner_name) = owner_name {
+        change_owner(&transaction, schema, owner_name).await?;
+    }
+
+    transaction.commit().await?;
+
+    info!("Copied {} tables and {} functions from template {} to schema {}", tables.len(), functions.len(), template, schema);
+
+    Ok(())
+}
+
+async fn change_owner(transaction: &Transaction<'_>, schema: &str, owner_name: &str) -> anyhow::Result<()> {
+    // Sequences owned by a column follow the owner of their table
+    let relations = transaction.query("
+        SELECT quote_ident(c.relname), c.relkind::text
+        FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1
+            AND c.relkind IN ('r', 'p', 'S', 'v', 'm')
+            AND NOT EXISTS (SELECT 1 FROM pg_depend d WHERE d.objid = c.oid AND d.deptype IN ('a', 'i'))", &[&schema]).await?;
+
+    for relation in relations {
+        let name: &str = relation.get(0);
+        let kind = match relation.get::<_, &str>(1) {
+            "S" => "SEQUENCE",
+            "v" => "VIEW",
+            "m" => "MATERIALIZED VIEW",
+            _ => "TABLE",
+        };
+        transaction.batch_execute(&format!("ALTER {} {}.{} OWNER TO {}", kind, schema, name, owner_name)).await?;
+    }
+
+    let routines = transaction.query("
+        SELECT quote_ident(p.proname), pg_get_function_identity_arguments(p.oid)
+        FROM pg_proc p
+            JOIN pg_namespace n ON n.oid = p.pronamespace
+        WHERE n.nspname = $1 AND p.prokind IN ('f', 'p')", &[&schema]).await?;
+
+    for routine in routines {
+        let name: &str = routine.get(0);
+        let arguments: &str = routine.get(1);
+        transaction.batch_execute(&format!("ALTER ROUTINE {}.{}({}) OWNER TO {}", schema, name, arguments, owner_name)).await?;
+    }
+
+    Ok(())
+}
+
+/// Replaces `template.` with `schema.` where the template name is not part of a longer identifier.
+fn rewrite_schema_references(definition: &str, template: &str, schema: &str) -> String {
+    let prefix = format!("{}.", template);
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_' || c == '$' || c == '.';
+
+    let mut result = String::with_capacity(definition.len());
+    let mut rest = definition;
+    while let Some(index) = rest.find(&prefix) {
+        let (before, after) = rest.split_at(index);
+        result.push_str(before);
+        let preceding = result.chars().last();
+        if preceding.is_some_and(is_identifier) {
+            result.push_str(&prefix);
+        } else {
+            result.push_str(schema);
+            result.push('.');
+        }
+        rest = &after[prefix.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+async fn query_names(transaction: &Transaction<'_>, query: &str, schema: &str) -> anyhow::Result<Vec<String>> {
+    Ok(transaction.query(query, &[&schema]).await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_rewrite_schema_references() {
+        let definition = "CREATE OR REPLACE FUNCTION template.next_id()\n RETURNS bigint\nAS $function$SELECT max(id) + 1 FROM template.items JOIN other_template.items USING (id)$function$";
+        assert_eq!(
+            super::rewrite_schema_references(definition, "template", "tenant"),
+            "CREATE OR REPLACE FUNCTION tenant.next_id()\n RETURNS bigint\nAS $function$SELECT max(id) + 1 FROM tenant.items JOIN other_template.items USING (id)$function$"
+        );
+    }
+}
@@ -4,9 +4,22 @@ mod postgres_role;
 mod pg_bouncer;
 mod pg_bouncer_database;
 mod pg_bouncer_user;
+mod postgres_tenant;
+mod postgres_publication;
+mod postgres_subscription;
+mod postgres_replication_slot;
+mod postgres_foreign_server;
+mod postgres_user_mapping;
+mod postgres_cron_job;
+mod postgres_policy;
+mod postgres_sql_job;
+mod postgres_database_settings;
+mod postgres_query_guard;
+mod postgres_maintenance;
 
 
 use std::fmt::{Display, Formatter};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 pub use postgres_schema::*;
@@ -15,6 +28,18 @@ pub use postgres_role::*;
 pub use pg_bouncer::*;
 pub use pg_bouncer_database::*;
 pub use pg_bouncer_user::*;
+pub use postgres_tenant::*;
+pub use postgres_publication::*;
+pub use postgres_subscription::*;
+pub use postgres_replication_slot::*;
+pub use postgres_foreign_server::*;
+pub use postgres_user_mapping::*;
+pub use postgres_cron_job::*;
+pub use postgres_policy::*;
+pub use postgres_sql_job::*;
+pub use postgres_database_settings::*;
+pub use postgres_query_guard::*;
+pub use postgres_maintenance::*;
 
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
@@ -44,3 +69,28 @@ impl Display for PostgresSslMode {
     }
 }
 
+
+/// What happens to the objects in Postgres when the kubernetes resource is deleted.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, JsonSchema, Default)]
+pub enum DeletionPolicy {
+    /// Drop the objects from Postgres.
+    #[default]
+    Delete,
+    /// Leave the objects in Postgres, and only remove the kubernetes resource.
+    Retain,
+}
+
+
+/// A status with fields that change on every reconcile, like the progress of replication.
+pub trait StatusProgress: Clone + PartialEq {
+    /// The status with the progress fields cleared. Defaults to all of it, for statuses without
+    /// progress.
+    fn without_progress(&self) -> Self {
+        self.clone()
+    }
+
+    /// When the progress was last written.
+    fn progress_time(&self) -> Option<&Time> {
+        None
+    }
+}
@@ -1,12 +1,12 @@
 use std::collections::BTreeMap;
 use std::fmt::{Debug, Display, Formatter};
-use k8s_openapi::api::core::v1::ResourceRequirements;
+use k8s_openapi::api::core::v1::{Affinity, Container, EnvVar, PodSecurityContext, ResourceRequirements, SecurityContext, Toleration, TopologySpreadConstraint};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use kube::{CustomResource, ResourceExt};
 use kube_runtime::reflector::ObjectRef;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::postgres_password::PostgresPassword;
-use crate::types::{PostgresSslMode};
+use crate::types::{PostgresRoleReference, PostgresSslMode};
 
 
 #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
@@ -25,6 +25,91 @@ pub struct PgBouncerSpec {
     pub pg_bouncer: PgBouncerSettings,
     pub pod_options: Option<PgBouncerPodOptions>,
     pub service: PgBouncerServiceSettings,
+    /// When any rules are given, on the bouncer, its users or its databases, `auth_type` is set
+    /// to `hba`. Rules of users and databases come before these.
+    pub hba_rules: Option<Vec<PgBouncerHbaRule>>,
+    pub tls: Option<PgBouncerTls>,
+    /// Drains pg_bouncer through the admin console before its pods are stopped. Requires `psql`
+    /// and `pg_isready` in the image. When clients have to present a certificate, the operator
+    /// admin user logs in with one issued through `tls.certificate`.
+    pub shutdown: Option<PgBouncerShutdown>,
+    /// Defaults to 1, and is ignored when autoscaling. `max_db_connections` of the bouncer and
+    /// its databases are divided by the number of replicas, or the maximum number of replicas
+    /// when autoscaling.
+    pub replicas: Option<i32>,
+    pub autoscaling: Option<PgBouncerAutoscaling>,
+    /// Without it no pod disruption budget is created. Defaults to `maxUnavailable: 1` when
+    /// neither `minAvailable` nor `maxUnavailable` is given.
+    pub pod_disruption_budget: Option<PgBouncerPodDisruptionBudget>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PgBouncerAutoscaling {
+    /// Defaults to 1.
+    pub min_replicas: Option<i32>,
+    pub max_replicas: i32,
+    /// Defaults to 80 when no memory target is given either. Needs a cpu request in
+    /// `podOptions.resources`.
+    pub target_cpu_utilization_percentage: Option<i32>,
+    /// Needs a memory request in `podOptions.resources`.
+    pub target_memory_utilization_percentage: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PgBouncerPodDisruptionBudget {
+    pub min_available: Option<IntOrString>,
+    pub max_unavailable: Option<IntOrString>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PgBouncerShutdown {
+    /// Defaults to `waitForClients`.
+    pub mode: Option<PgBouncerShutdownMode>,
+    /// Seconds the pod is reported as not ready before pg_bouncer is shut down, so the service
+    /// stops routing new clients to it first. Defaults to 10.
+    pub drain_delay_seconds: Option<u32>,
+    /// Defaults to 120.
+    pub termination_grace_period_seconds: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PgBouncerShutdownMode {
+    /// `SHUTDOWN WAIT_FOR_CLIENTS`, pg_bouncer exits once all clients have disconnected.
+    /// Requires pg_bouncer 1.23 or newer.
+    #[default]
+    WaitForClients,
+    /// `PAUSE` until all running queries are done, then `SHUTDOWN`.
+    Pause,
+}
+
+/// The secrets are expected to use the keys of `kubernetes.io/tls` secrets, `tls.key`,
+/// `tls.crt` and `ca.crt`, and the files that are present are configured.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PgBouncerTls {
+    /// The secret with the certificate presented to clients.
+    pub client_secret_name: Option<String>,
+    /// The secret with the client certificate presented to Postgres, and the CA Postgres is
+    /// verified with.
+    pub server_secret_name: Option<String>,
+    /// Creates a cert-manager Certificate for the service, used as the client certificate.
+    pub certificate: Option<PgBouncerCertificate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PgBouncerCertificate {
+    pub issuer_name: String,
+    /// Defaults to `Issuer`.
+    pub issuer_kind: Option<String>,
+    /// Defaults to `cert-manager.io`.
+    pub issuer_group: Option<String>,
+    /// Extra DNS names besides the ones of the service.
+    pub dns_names: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
@@ -83,7 +168,25 @@ pub struct PgBouncerPodOptions {
     /// A pull secret to use for the custom image. Do note pg-bouncer is started in the same
     /// namespace as this CRD instance, meaning the pull secret should be in the same
     /// namespace as this CRD instance.
-    pub image_pull_secret: Option<String>
+    pub image_pull_secret: Option<String>,
+
+    pub tolerations: Option<Vec<Toleration>>,
+    pub affinity: Option<Affinity>,
+    /// Constraints without a label selector select the pods of this bouncer.
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+    pub priority_class_name: Option<String>,
+    pub service_account_name: Option<String>,
+    /// An empty dir is mounted at `/tmp`, so a read only root file system can be used.
+    pub security_context: Option<PodSecurityContext>,
+    pub container_security_context: Option<SecurityContext>,
+    /// Extra labels of the pods, the labels used by the operator cannot be overridden.
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Extra annotations of the pods, the annotations used by the operator cannot be overridden.
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// Extra environment variables of the pg-bouncer container.
+    pub env: Option<Vec<EnvVar>>,
+    /// Extra containers run next to pg-bouncer.
+    pub sidecars: Option<Vec<Container>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
@@ -95,21 +198,152 @@ pub struct PgBouncerSettings {
     pub ignore_startup_parameters: Option<Vec<String>>,
     pub server_tls_ssl_mode: PostgresSslMode,
     pub client_tls_ssl_mode: PostgresSslMode,
+    /// The role pg_bouncer looks up users with, using `auth_query`, so users do not need to be
+    /// registered as PgBouncerUsers. The lookup function is created in every database that has
+    /// a connection, and the password of the role has to be given in plain text.
+    pub auth_user: Option<PostgresRoleReference>,
     pub max_client_conn: u32,
     pub max_db_connections: u32,
+    pub max_user_connections: Option<u32>,
+
+    pub default_pool_size: Option<u32>,
+    pub min_pool_size: Option<u32>,
+    pub reserve_pool_size: Option<u32>,
+    /// In seconds.
+    pub reserve_pool_timeout: Option<u32>,
+    /// Enables support for prepared statements in transaction and statement pooling mode.
+    pub max_prepared_statements: Option<u32>,
+
+    pub server_reset_query: Option<String>,
+    pub server_reset_query_always: Option<bool>,
+    pub server_check_query: Option<String>,
+    /// In seconds.
+    pub server_check_delay: Option<u32>,
+    pub server_round_robin: Option<bool>,
+
+    /// In seconds.
+    pub server_lifetime: Option<u32>,
+    /// In seconds.
+    pub server_idle_timeout: Option<u32>,
+    /// In seconds.
+    pub server_connect_timeout: Option<u32>,
+    /// In seconds.
+    pub server_login_retry: Option<u32>,
+    /// In seconds.
+    pub query_timeout: Option<u32>,
+    /// In seconds.
+    pub query_wait_timeout: Option<u32>,
+    /// In seconds.
+    pub client_idle_timeout: Option<u32>,
+    /// In seconds.
+    pub client_login_timeout: Option<u32>,
+    /// In seconds.
+    pub idle_transaction_timeout: Option<u32>,
+
+    pub stats_users: Option<Vec<String>>,
+    /// In seconds.
+    pub stats_period: Option<u32>,
+    pub log_connections: Option<bool>,
+    pub log_disconnections: Option<bool>,
+    pub log_pooler_errors: Option<bool>,
+    pub log_stats: Option<bool>,
+    pub verbose: Option<u32>,
+    pub application_name_add_host: Option<bool>,
+    pub tcp_keepalive: Option<bool>,
+
+    /// Settings written to the `[pgbouncer]` section as is, for settings that are not
+    /// modelled above. They cannot override settings configured above or managed by the operator.
+    pub extra_settings: Option<BTreeMap<String, String>>,
 }
 
+/// A line of the `auth_hba_file`.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
-pub struct PgBouncerAuthUser {
-    pub username: String,
-    pub password: PostgresPassword,
+pub struct PgBouncerHbaRule {
+    /// Defaults to `host`.
+    pub connection_type: Option<PgBouncerHbaConnectionType>,
+    /// Defaults to all databases. Ignored for rules of a PgBouncerDatabase.
+    pub databases: Option<Vec<String>>,
+    /// Defaults to all users. Ignored for rules of a PgBouncerUser.
+    pub users: Option<Vec<String>>,
+    /// A CIDR, like `10.0.0.0/8`.
+    pub address: String,
+    pub method: PgBouncerHbaMethod,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PgBouncerHbaConnectionType {
+    #[default]
+    Host,
+    HostSsl,
+    HostNoSsl,
+}
+
+impl Display for PgBouncerHbaConnectionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PgBouncerHbaConnectionType::Host => "host",
+            PgBouncerHbaConnectionType::HostSsl => "hostssl",
+            PgBouncerHbaConnectionType::HostNoSsl => "hostnossl",
+        };
+
+        f.write_str(s)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum PgBouncerHbaMethod {
+    Trust,
+    Reject,
+    Password,
+    Md5,
+    ScramSha256,
+    Cert,
+}
+
+impl Display for PgBouncerHbaMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PgBouncerHbaMethod::Trust => "trust",
+            PgBouncerHbaMethod::Reject => "reject",
+            PgBouncerHbaMethod::Password => "password",
+            PgBouncerHbaMethod::Md5 => "md5",
+            PgBouncerHbaMethod::ScramSha256 => "scram-sha-256",
+            PgBouncerHbaMethod::Cert => "cert",
+        };
+
+        f.write_str(s)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct PgBouncerStatus {
     pub last_user_config_hash: Option<String>,
+    /// Hash of all rendered config files.
+    pub config_hash: Option<String>,
+    /// When the config hash last changed. Pods are reloaded a while after the change, as kubelet
+    /// syncs the mounted files with a delay.
+    pub config_changed_time: Option<String>,
+    /// The config hash the pods were last restarted for, when reloading did not work.
+    pub restarted_config_hash: Option<String>,
+    pub pods: Option<Vec<PgBouncerPodStatus>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PgBouncerPodStatus {
+    pub name: String,
+    /// Hash of the config the pod was last verified to have loaded, through its admin console.
+    pub loaded_config_hash: Option<String>,
+    /// Hash of the config `RELOAD` was last sent to the pod for.
+    pub reloaded_config_hash: Option<String>,
+    pub up_to_date: bool,
+    /// How the config loaded by the pod differs from the expected config.
+    pub differences: Option<Vec<String>>,
+    pub error: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
@@ -141,6 +375,11 @@ pub enum PgBouncerAuthType {
     Plain,
     Md5,
     ScramSha256,
+    /// Set automatically when hba rules are given.
+    Hba,
+    /// Users authenticate with TLS client certificates. Requires a client CA, and a client
+    /// TLS mode of `verify-ca` or `verify-full`.
+    Cert,
 }
 
 impl Display for PgBouncerAuthType {
@@ -149,6 +388,8 @@ impl Display for PgBouncerAuthType {
             PgBouncerAuthType::Plain => "plain",
             PgBouncerAuthType::Md5 => "md5",
             PgBouncerAuthType::ScramSha256 => "scram-sha-256",
+            PgBouncerAuthType::Hba => "hba",
+            PgBouncerAuthType::Cert => "cert",
         };
 
 
@@ -1,7 +1,7 @@
 use kube::{CustomResource};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::types::{HasPgBouncerReference, PgBouncerReference};
+use crate::types::{HasPgBouncerReference, PgBouncerHbaRule, PgBouncerPoolMode, PgBouncerReference, PostgresAdminConnectionReference};
 
 
 #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
@@ -23,6 +23,26 @@ pub struct PgBouncerDatabaseSpec {
     pub port: Option<u16>,
     pub user: Option<String>,
     pub pg_bouncer: PgBouncerReference,
+
+    /// Overrides the `default_pool_size` of the bouncer.
+    pub pool_size: Option<u32>,
+    pub min_pool_size: Option<u32>,
+    pub reserve_pool: Option<u32>,
+    /// Overrides the pool mode of the bouncer.
+    pub pool_mode: Option<PgBouncerPoolMode>,
+    /// Caps the server connections to this database, across all users.
+    pub max_db_connections: Option<u32>,
+    /// A query run on every new server connection.
+    pub connect_query: Option<String>,
+    pub client_encoding: Option<String>,
+    pub datestyle: Option<String>,
+    pub timezone: Option<String>,
+    pub auth_user: Option<String>,
+    /// An admin connection to the database, used to create the user lookup function when the
+    /// bouncer has an auth user. It has to point at the same database as the bouncer.
+    pub connection: Option<PostgresAdminConnectionReference>,
+    /// Rules that only apply to this database.
+    pub hba_rules: Option<Vec<PgBouncerHbaRule>>,
 }
 
 
@@ -2,7 +2,7 @@ use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::postgres_password::PostgresPassword;
-use crate::types::{HasPgBouncerReference, PgBouncerReference};
+use crate::types::{HasPgBouncerReference, PgBouncerHbaRule, PgBouncerPoolMode, PgBouncerReference};
 
 
 #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
@@ -19,8 +19,33 @@ use crate::types::{HasPgBouncerReference, PgBouncerReference};
 #[serde(rename_all = "camelCase")]
 pub struct PgBouncerUserSpec {
     pub username: String,
-    pub password: PostgresPassword,
+    /// Required unless the user authenticates with a client certificate.
+    pub password: Option<PostgresPassword>,
+    /// The user authenticates with a TLS client certificate whose CN is the username, and is
+    /// written to the userlist without a password. Postgres then has to accept the user
+    /// without a password, or through the server certificate.
+    pub cert: Option<bool>,
     pub pg_bouncer: PgBouncerReference,
+
+    /// Overrides the pool mode of the bouncer and the database.
+    pub pool_mode: Option<PgBouncerPoolMode>,
+    /// Caps the server connections of the user, across all databases.
+    pub max_user_connections: Option<u32>,
+    /// Overrides the pool size of the bouncer and the database.
+    pub pool_size: Option<u32>,
+    /// Rules that only apply to this user.
+    pub hba_rules: Option<Vec<PgBouncerHbaRule>>,
+}
+
+/// Registers a role as a user in a pg bouncer, with optional per-user settings.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PgBouncerRegistration {
+    #[serde(flatten)]
+    pub pg_bouncer: PgBouncerReference,
+    pub pool_mode: Option<PgBouncerPoolMode>,
+    pub max_user_connections: Option<u32>,
+    pub pool_size: Option<u32>,
 }
 
 impl HasPgBouncerReference for PgBouncerUser {
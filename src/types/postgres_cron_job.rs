@@ -0,0 +1,51 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, PostgresRoleReference};
+
+
+/// A job scheduled with the pg_cron extension. The admin connection has to point at the
+/// database pg_cron is installed in.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresCronJob",
+    plural = "postgrescronjobs",
+    derive = "PartialEq",
+    status = "PostgresCronJobStatus",
+    printcolumn = r#"{"name":"Job", "type":"string", "description":"Name of the job", "jsonPath":".spec.jobName"}"#,
+    printcolumn = r#"{"name":"Schedule", "type":"string", "description":"Cron schedule of the job", "jsonPath":".spec.schedule"}"#,
+    printcolumn = r#"{"name":"Last run", "type":"string", "description":"Outcome of the last run", "jsonPath":".status.lastRunStatus"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresCronJobSpec {
+    pub job_name: String,
+    /// A cron schedule, e.g. `*/5 * * * *`, or an interval like `30 seconds`.
+    pub schedule: String,
+    pub command: String,
+    /// The database to run the command in. Defaults to the database of the admin connection.
+    pub database: Option<String>,
+    /// The role to run the command as. Defaults to the admin user.
+    pub run_as: Option<PostgresRoleReference>,
+    /// Defaults to true.
+    pub active: Option<bool>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresCronJob {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresCronJobStatus {
+    pub job_id: Option<i64>,
+    pub last_run_status: Option<String>,
+    pub last_run_message: Option<String>,
+    pub last_run_start_time: Option<String>,
+    pub last_run_end_time: Option<String>,
+}
@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference};
+
+
+/// Database level parameters and connection settings. Setting `allowConnections` to false puts
+/// the database in maintenance mode.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresDatabaseSettings",
+    plural = "postgresdatabasesettings",
+    derive = "PartialEq",
+    status = "PostgresDatabaseSettingsStatus",
+    printcolumn = r#"{"name":"Database", "type":"string", "description":"Name of the database", "jsonPath":".spec.database"}"#,
+    printcolumn = r#"{"name":"Allow connections", "type":"boolean", "description":"If connections to the database are allowed", "jsonPath":".spec.allowConnections"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresDatabaseSettingsSpec {
+    /// Defaults to the database of the admin connection.
+    pub database: Option<String>,
+    /// Parameters set with `ALTER DATABASE ... SET`. The list parameters `search_path`,
+    /// `temp_tablespaces`, `local_preload_libraries` and `session_preload_libraries` are given
+    /// comma separated, other values are set as is.
+    pub parameters: Option<BTreeMap<String, String>>,
+    /// Setting this to false also terminates all existing connections to the database. The admin
+    /// connection has to point at another database, as it can no longer connect either.
+    pub allow_connections: Option<bool>,
+    /// -1 means no limit.
+    pub connection_limit: Option<i32>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresDatabaseSettings {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresDatabaseSettingsStatus {
+    pub ready: bool,
+    /// The parameters set by the operator, which are reset when removed from the spec.
+    pub managed_parameters: Option<Vec<String>>,
+    /// The number of backends terminated the last time connections were disallowed.
+    pub terminated_backends: Option<i64>,
+    /// If the backends connected when connections were disallowed have been terminated.
+    pub backends_terminated: Option<bool>,
+}
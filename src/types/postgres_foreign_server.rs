@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference};
+
+
+/// A postgres_fdw foreign server pointing at another Postgres database.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresForeignServer",
+    plural = "postgresforeignservers",
+    derive = "PartialEq",
+    status = "PostgresForeignServerStatus",
+    printcolumn = r#"{"name":"Server", "type":"string", "description":"Name of the foreign server", "jsonPath":".spec.serverName"}"#,
+    printcolumn = r#"{"name":"Host", "type":"string", "description":"Remote host", "jsonPath":".spec.host"}"#,
+    printcolumn = r#"{"name":"Database", "type":"string", "description":"Remote database", "jsonPath":".spec.dbname"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresForeignServerSpec {
+    pub server_name: String,
+    pub host: String,
+    pub port: Option<u16>,
+    pub dbname: String,
+    /// Additional postgres_fdw server options, e.g. `fetch_size` or `use_remote_estimate`.
+    pub options: Option<BTreeMap<String, String>>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresForeignServer {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresForeignServerStatus {
+    pub ready: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresForeignServerReference {
+    pub name: String,
+    pub namespace: Option<String>,
+}
@@ -0,0 +1,90 @@
+use std::fmt::{Display, Formatter};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, PostgresSchemaReference};
+
+
+/// Runs `VACUUM`, `ANALYZE` or `REINDEX` on a schedule.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresMaintenance",
+    plural = "postgresmaintenances",
+    derive = "PartialEq",
+    status = "PostgresMaintenanceStatus",
+    printcolumn = r#"{"name":"Operation", "type":"string", "description":"The maintenance operation", "jsonPath":".spec.operation"}"#,
+    printcolumn = r#"{"name":"Schedule", "type":"string", "description":"When the maintenance runs", "jsonPath":".spec.schedule"}"#,
+    printcolumn = r#"{"name":"Next run", "type":"string", "description":"When the maintenance runs next", "jsonPath":".status.nextRunTime"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresMaintenanceSpec {
+    /// Every table in the schema is maintained. Either this or `tables` has to be given.
+    pub schema: Option<PostgresSchemaReference>,
+    /// Schema qualified names of the tables to maintain.
+    pub tables: Option<Vec<String>>,
+    pub operation: PostgresMaintenanceOperation,
+    /// A cron expression in UTC, either with five fields, or six with seconds first.
+    pub schedule: String,
+    /// Scheduled runs outside the window are skipped, and a run stops between tables when the
+    /// window closes.
+    pub window: Option<PostgresMaintenanceWindow>,
+    /// Passed to `SET lock_timeout`, for example `5s`.
+    pub lock_timeout: Option<String>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresMaintenance {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PostgresMaintenanceOperation {
+    VacuumAnalyze,
+    Analyze,
+    /// Rebuilds the indexes concurrently.
+    Reindex,
+}
+
+impl Display for PostgresMaintenanceOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostgresMaintenanceOperation::VacuumAnalyze => write!(f, "VACUUM (ANALYZE)"),
+            PostgresMaintenanceOperation::Analyze => write!(f, "ANALYZE"),
+            PostgresMaintenanceOperation::Reindex => write!(f, "REINDEX TABLE CONCURRENTLY"),
+        }
+    }
+}
+
+/// A daily window in UTC, in `HH:MM` form. The window may cross midnight.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresMaintenanceWindow {
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresMaintenanceStatus {
+    /// The last scheduled time that was either run or skipped.
+    pub last_scheduled_time: Option<String>,
+    pub next_run_time: Option<String>,
+    /// The most recent runs, newest first.
+    pub runs: Option<Vec<PostgresMaintenanceRun>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresMaintenanceRun {
+    pub start_time: String,
+    pub duration_seconds: f64,
+    pub succeeded: bool,
+    pub tables: u32,
+    pub error: Option<String>,
+}
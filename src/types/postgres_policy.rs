@@ -0,0 +1,105 @@
+use std::fmt::{Display, Formatter};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, PostgresSchemaReference};
+
+
+/// Enables row level security on a table and converges its policies to the declared ones.
+/// Policies on the table that are not declared are dropped.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresPolicy",
+    plural = "postgrespolicies",
+    derive = "PartialEq",
+    status = "PostgresPolicyStatus",
+    printcolumn = r#"{"name":"Table", "type":"string", "description":"Name of the table", "jsonPath":".spec.table"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresPolicySpec {
+    pub schema: PostgresSchemaReference,
+    pub table: String,
+    /// Also apply the policies to the owner of the table.
+    pub force: Option<bool>,
+    pub policies: Vec<PostgresPolicyDefinition>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresPolicy {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresPolicyDefinition {
+    pub name: String,
+    /// Defaults to all commands.
+    pub command: Option<PostgresPolicyCommand>,
+    /// Defaults to permissive.
+    pub kind: Option<PostgresPolicyKind>,
+    /// Defaults to `PUBLIC`.
+    pub roles: Option<Vec<String>>,
+    /// The USING expression, checked for existing rows.
+    pub using: Option<String>,
+    /// The WITH CHECK expression, checked for new rows.
+    pub with_check: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PostgresPolicyCommand {
+    #[default]
+    All,
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Display for PostgresPolicyCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PostgresPolicyCommand::All => "ALL",
+            PostgresPolicyCommand::Select => "SELECT",
+            PostgresPolicyCommand::Insert => "INSERT",
+            PostgresPolicyCommand::Update => "UPDATE",
+            PostgresPolicyCommand::Delete => "DELETE",
+        };
+
+        f.write_str(s)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PostgresPolicyKind {
+    #[default]
+    Permissive,
+    Restrictive,
+}
+
+impl Display for PostgresPolicyKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PostgresPolicyKind::Permissive => "PERMISSIVE",
+            PostgresPolicyKind::Restrictive => "RESTRICTIVE",
+        };
+
+        f.write_str(s)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresPolicyStatus {
+    pub ready: bool,
+    /// The schema the policies were applied in, so they can be dropped after the schema
+    /// resource is gone.
+    pub schema_name: Option<String>,
+    pub policies: Option<Vec<String>>,
+}
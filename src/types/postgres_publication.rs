@@ -0,0 +1,66 @@
+use std::fmt::{Display, Formatter};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference};
+
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresPublication",
+    plural = "postgrespublications",
+    derive = "PartialEq",
+    status = "PostgresPublicationStatus",
+    printcolumn = r#"{"name":"Publication", "type":"string", "description":"Name of the publication", "jsonPath":".spec.publication"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresPublicationSpec {
+    pub publication: String,
+    /// Publish all tables in the database, including tables created later.
+    /// Cannot be combined with `tables` or `schemas`.
+    pub all_tables: Option<bool>,
+    /// Tables to publish, optionally qualified with their schema.
+    pub tables: Option<Vec<String>>,
+    /// Schemas to publish all tables of. Requires Postgres 15 or later.
+    pub schemas: Option<Vec<String>>,
+    /// The operations to publish. Defaults to all operations.
+    pub publish: Option<Vec<PostgresPublicationOperation>>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresPublication {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresPublicationStatus {
+    pub ready: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PostgresPublicationOperation {
+    Insert,
+    Update,
+    Delete,
+    Truncate,
+}
+
+impl Display for PostgresPublicationOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PostgresPublicationOperation::Insert => "insert",
+            PostgresPublicationOperation::Update => "update",
+            PostgresPublicationOperation::Delete => "delete",
+            PostgresPublicationOperation::Truncate => "truncate",
+        };
+
+        f.write_str(s)
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt::{Display, Formatter};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, StatusProgress};
+
+
+/// Cancels or terminates long-running queries and idle transactions of the given roles or
+/// databases.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresQueryGuard",
+    plural = "postgresqueryguards",
+    derive = "PartialEq",
+    status = "PostgresQueryGuardStatus",
+    printcolumn = r#"{"name":"Cancelled", "type":"integer", "description":"Number of cancelled queries", "jsonPath":".status.cancelledQueries"}"#,
+    printcolumn = r#"{"name":"Terminated", "type":"integer", "description":"Number of terminated sessions", "jsonPath":".status.terminatedSessions"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresQueryGuardSpec {
+    /// Only sessions of these roles are guarded. At least one role or database has to be given.
+    pub roles: Option<Vec<String>>,
+    /// Only sessions connected to these databases are guarded.
+    pub databases: Option<Vec<String>>,
+    /// How often sessions are inspected. Defaults to 60 seconds.
+    pub interval_seconds: Option<u64>,
+    pub max_query_duration_seconds: Option<u64>,
+    /// Sessions idle in a transaction for longer than this are always terminated, as there is
+    /// no query to cancel.
+    pub max_idle_in_transaction_seconds: Option<u64>,
+    /// What to do with queries running for too long. Defaults to `cancel`.
+    pub action: Option<PostgresQueryGuardAction>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresQueryGuard {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PostgresQueryGuardAction {
+    #[default]
+    Cancel,
+    Terminate,
+}
+
+impl Display for PostgresQueryGuardAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostgresQueryGuardAction::Cancel => write!(f, "Cancel"),
+            PostgresQueryGuardAction::Terminate => write!(f, "Terminate"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresQueryGuardStatus {
+    pub cancelled_queries: Option<i64>,
+    pub terminated_sessions: Option<i64>,
+}
+
+impl StatusProgress for PostgresQueryGuardStatus {}
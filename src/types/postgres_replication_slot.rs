@@ -0,0 +1,72 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, StatusProgress};
+
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresReplicationSlot",
+    plural = "postgresreplicationslots",
+    derive = "PartialEq",
+    status = "PostgresReplicationSlotStatus",
+    printcolumn = r#"{"name":"Slot", "type":"string", "description":"Name of the replication slot", "jsonPath":".spec.slotName"}"#,
+    printcolumn = r#"{"name":"Active", "type":"boolean", "description":"If a consumer is connected to the slot", "jsonPath":".status.active"}"#,
+    printcolumn = r#"{"name":"Retained WAL", "type":"integer", "description":"Bytes of WAL retained by the slot", "jsonPath":".status.retainedWalBytes"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresReplicationSlotSpec {
+    pub slot_name: String,
+    pub slot_type: PostgresReplicationSlotType,
+    /// The output plugin of a logical slot. Defaults to `pgoutput`.
+    pub plugin: Option<String>,
+    /// When the slot is inactive and retains more WAL than this, the `ExcessiveWalRetention`
+    /// condition is raised.
+    pub max_inactive_retained_wal_bytes: Option<i64>,
+    /// Logical slots are created in the database of the admin connection.
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresReplicationSlot {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PostgresReplicationSlotType {
+    #[default]
+    Physical,
+    Logical,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresReplicationSlotStatus {
+    pub active: Option<bool>,
+    pub restart_lsn: Option<String>,
+    pub retained_wal_bytes: Option<i64>,
+    pub conditions: Option<Vec<Condition>>,
+    /// When the restart LSN and the retained WAL were last written, which is at most once a
+    /// minute.
+    pub last_progress_time: Option<Time>,
+}
+
+impl StatusProgress for PostgresReplicationSlotStatus {
+    fn without_progress(&self) -> Self {
+        PostgresReplicationSlotStatus {
+            active: self.active,
+            conditions: self.conditions.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn progress_time(&self) -> Option<&Time> {
+        self.last_progress_time.as_ref()
+    }
+}
@@ -2,7 +2,7 @@ use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::postgres_password::PostgresPassword;
-use crate::types::{HasPostgresAdminConnection, PgBouncerReference, PostgresAdminConnectionReference};
+use crate::types::{DeletionPolicy, HasPostgresAdminConnection, PgBouncerRegistration, PostgresAdminConnectionReference};
 
 
 #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
@@ -20,8 +20,11 @@ use crate::types::{HasPostgresAdminConnection, PgBouncerReference, PostgresAdmin
 pub struct PostgresRoleSpec {
     pub role: String,
     pub password: PostgresPassword,
-    pub register_in_pg_bouncer: Option<PgBouncerReference>,
+    pub register_in_pg_bouncer: Option<PgBouncerRegistration>,
     pub grant_role_to_admin_user: Option<bool>,
+    /// Schemas set as the default search_path of the role.
+    pub search_path: Option<Vec<String>>,
+    pub deletion_policy: Option<DeletionPolicy>,
     pub connection: PostgresAdminConnectionReference,
 }
 
@@ -35,6 +38,10 @@ impl HasPostgresAdminConnection for PostgresRole {
 #[serde(rename_all = "camelCase")]
 pub struct PostgresRoleStatus {
     pub encoded_password: Option<StatusEncodedPassword>,
+    #[serde(default)]
+    pub ready: bool,
+    /// The search_path last set on the role, which is reset when removed from the spec.
+    pub search_path: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
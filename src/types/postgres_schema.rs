@@ -1,7 +1,7 @@
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, PostgresRoleReference};
+use crate::types::{DeletionPolicy, HasPostgresAdminConnection, PostgresAdminConnectionReference, PostgresRoleReference};
 
 
 #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
@@ -13,6 +13,7 @@ use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference,
     derive = "PartialEq",
     status = "PostgresSchemaStatus",
     printcolumn = r#"{"name":"Schema", "type":"string", "description":"Name of the schema", "jsonPath":".schema"}"#,
+    printcolumn = r#"{"name":"Version", "type":"string", "description":"Last applied migration", "jsonPath":".status.currentMigrationVersion"}"#,
     namespaced
 )]
 #[serde(rename_all = "camelCase")]
@@ -20,6 +21,12 @@ pub struct PostgresSchemaSpec {
     pub schema: String,
     pub schema_owner: Option<PostgresSchemaOwner>,
     pub connection: PostgresAdminConnectionReference,
+    /// A schema to copy tables, indexes, sequences, views and functions from when the schema is
+    /// first created. Later changes to the template are not propagated.
+    pub from_template: Option<String>,
+    /// SQL migrations to apply to the schema after it has been created.
+    pub migrations: Option<PostgresSchemaMigrations>,
+    pub deletion_policy: Option<DeletionPolicy>,
 }
 
 impl HasPostgresAdminConnection for PostgresSchema {
@@ -31,7 +38,11 @@ impl HasPostgresAdminConnection for PostgresSchema {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct PostgresSchemaStatus {
-
+    #[serde(default)]
+    pub ready: bool,
+    /// The highest migration version applied to the schema.
+    pub current_migration_version: Option<String>,
+    pub migration_error: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
@@ -39,4 +50,22 @@ pub struct PostgresSchemaStatus {
 pub enum PostgresSchemaOwner {
     ManagedRole(PostgresRoleReference),
     Name(String),
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSchemaMigrations {
+    /// A config map in the same namespace as the schema. Every key is a migration version,
+    /// and migrations are applied in the lexicographic order of their keys, e.g.
+    /// `0001_create_users.sql`, `0002_add_email.sql`.
+    pub config_map: String,
+    /// The table inside the schema used to track applied migrations. Defaults to `__migrations`.
+    pub tracking_table: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSchemaReference {
+    pub name: String,
+    pub namespace: Option<String>,
+}
@@ -0,0 +1,71 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, PostgresRoleReference};
+
+
+/// A SQL script that is run once in a transaction. The script is run again when it, the role it
+/// runs as or the server and database it runs in changes.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresSqlJob",
+    plural = "postgressqljobs",
+    derive = "PartialEq",
+    status = "PostgresSqlJobStatus",
+    printcolumn = r#"{"name":"Succeeded", "type":"boolean", "description":"If the script succeeded", "jsonPath":".status.succeeded"}"#,
+    printcolumn = r#"{"name":"Attempts", "type":"integer", "description":"Number of times the script has been run", "jsonPath":".status.attempts"}"#,
+    printcolumn = r#"{"name":"Rows", "type":"integer", "description":"Number of rows affected by the script", "jsonPath":".status.rowsAffected"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSqlJobSpec {
+    pub script: PostgresSqlJobScript,
+    /// Runs the script as this role using `SET ROLE`, instead of as the admin user.
+    pub run_as: Option<PostgresRoleReference>,
+    /// Without a retry policy, failed scripts are not retried.
+    pub retry_policy: Option<PostgresSqlJobRetryPolicy>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresSqlJob {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PostgresSqlJobScript {
+    Inline(String),
+    /// A key in a config map in the same namespace as the job.
+    ConfigMap(PostgresSqlJobConfigMapKey),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSqlJobConfigMapKey {
+    pub name: String,
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSqlJobRetryPolicy {
+    pub max_retries: u32,
+    /// Seconds to wait between attempts. Defaults to 30.
+    pub backoff_seconds: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSqlJobStatus {
+    /// Hash of the script, role, server and database the status applies to.
+    pub spec_hash: Option<String>,
+    pub succeeded: Option<bool>,
+    pub attempts: Option<u32>,
+    pub error: Option<String>,
+    pub rows_affected: Option<u64>,
+    pub last_run_time: Option<String>,
+}
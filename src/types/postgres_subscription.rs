@@ -0,0 +1,78 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, StatusProgress};
+
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresSubscription",
+    plural = "postgressubscriptions",
+    derive = "PartialEq",
+    status = "PostgresSubscriptionStatus",
+    printcolumn = r#"{"name":"Subscription", "type":"string", "description":"Name of the subscription", "jsonPath":".spec.subscription"}"#,
+    printcolumn = r#"{"name":"Active", "type":"boolean", "description":"If the apply worker is running", "jsonPath":".status.active"}"#,
+    printcolumn = r#"{"name":"Lag", "type":"integer", "description":"Bytes of WAL not yet received from the publisher", "jsonPath":".status.lagBytes"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSubscriptionSpec {
+    pub subscription: String,
+    /// The publishing server. The credentials of the admin connection are used to replicate.
+    pub source: PostgresAdminConnectionReference,
+    pub publications: Vec<String>,
+    /// The replication slot on the publisher. Defaults to the name of the subscription.
+    pub slot_name: Option<String>,
+    /// If the replication slot should be created on the publisher. Defaults to true.
+    pub create_slot: Option<bool>,
+    /// If existing data in the published tables should be copied. Defaults to true.
+    pub copy_data: Option<bool>,
+    /// Defaults to true.
+    pub enabled: Option<bool>,
+    /// The subscribing server.
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresSubscription {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSubscriptionStatus {
+    pub ready: bool,
+    /// Hash of the connection string last used for the subscription, so changes to the source
+    /// can be detected without reading the connection string back.
+    pub connection_hash: Option<String>,
+    pub enabled: Option<bool>,
+    pub active: Option<bool>,
+    pub received_lsn: Option<String>,
+    pub latest_end_lsn: Option<String>,
+    pub last_message_receipt_time: Option<String>,
+    pub latest_end_time: Option<String>,
+    /// Bytes of WAL written on the publisher, but not yet received by the subscriber.
+    pub lag_bytes: Option<i64>,
+    /// When the LSNs and the lag were last written, which is at most once a minute.
+    pub last_progress_time: Option<Time>,
+}
+
+impl StatusProgress for PostgresSubscriptionStatus {
+    fn without_progress(&self) -> Self {
+        PostgresSubscriptionStatus {
+            ready: self.ready,
+            connection_hash: self.connection_hash.clone(),
+            enabled: self.enabled,
+            active: self.active,
+            ..Default::default()
+        }
+    }
+
+    fn progress_time(&self) -> Option<&Time> {
+        self.last_progress_time.as_ref()
+    }
+}
@@ -0,0 +1,46 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::postgres_password::PostgresPassword;
+use crate::types::{DeletionPolicy, PgBouncerRegistration, PostgresAdminConnectionReference};
+
+
+/// A role with a schema of its own, expanded into a PostgresRole, a PostgresSchema and
+/// optionally a PgBouncerUser, plus a secret with the connection details of the tenant.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresTenant",
+    plural = "postgrestenants",
+    derive = "PartialEq",
+    status = "PostgresTenantStatus",
+    printcolumn = r#"{"name":"Role", "type":"string", "description":"Name of the role", "jsonPath":".spec.role"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"boolean", "description":"If all parts of the tenant are ready", "jsonPath":".status.ready"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresTenantSpec {
+    pub role: String,
+    /// Must be given in plaintext, as it is written to the connection secret. It is still hashed
+    /// on the server for the md5 and scram-sha-256 variants.
+    pub password: PostgresPassword,
+    /// The schema owned by the role. Defaults to the name of the role.
+    pub schema: Option<String>,
+    pub from_template: Option<String>,
+    pub register_in_pg_bouncer: Option<PgBouncerRegistration>,
+    pub grant_role_to_admin_user: Option<bool>,
+    /// The secret the connection details are written to. Defaults to `<name>-connection`.
+    pub connection_secret_name: Option<String>,
+    /// Applies to both the role and the schema.
+    pub deletion_policy: Option<DeletionPolicy>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresTenantStatus {
+    pub ready: bool,
+    /// The kinds of the children that are not ready yet.
+    pub not_ready: Option<Vec<String>>,
+}
@@ -0,0 +1,66 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::types::{HasPostgresAdminConnection, PostgresAdminConnectionReference, PostgresForeignServerReference, PostgresRoleReference, PostgresSchemaReference};
+
+
+/// Maps a managed role to credentials on the remote side of a foreign server.
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(
+    group = "postgres.digizuite.com",
+    version = "v1alpha1",
+    kind = "PostgresUserMapping",
+    plural = "postgresusermappings",
+    derive = "PartialEq",
+    status = "PostgresUserMappingStatus",
+    printcolumn = r#"{"name":"Server", "type":"string", "description":"Name of the foreign server", "jsonPath":".status.serverName"}"#,
+    printcolumn = r#"{"name":"Role", "type":"string", "description":"Name of the local role", "jsonPath":".status.roleName"}"#,
+    namespaced
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresUserMappingSpec {
+    pub server: PostgresForeignServerReference,
+    pub role: PostgresRoleReference,
+    pub credentials: PostgresUserMappingCredentials,
+    /// Imports the tables of a remote schema as foreign tables owned by the role. Tables that
+    /// have already been imported are left alone, so new remote tables are imported over time.
+    pub import_foreign_schema: Option<PostgresImportForeignSchema>,
+    pub connection: PostgresAdminConnectionReference,
+}
+
+impl HasPostgresAdminConnection for PostgresUserMapping {
+    fn get_connection(&self) -> &PostgresAdminConnectionReference {
+        &self.spec.connection
+    }
+}
+
+/// A secret in the same namespace as the user mapping with the remote credentials.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresUserMappingCredentials {
+    pub secret_name: String,
+    /// Defaults to `username`.
+    pub username_key: Option<String>,
+    /// Defaults to `password`.
+    pub password_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresImportForeignSchema {
+    pub remote_schema: String,
+    /// The managed schema to create the foreign tables in. The role needs CREATE on it.
+    pub into: PostgresSchemaReference,
+    pub limit_to: Option<Vec<String>>,
+    pub except: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresUserMappingStatus {
+    pub ready: bool,
+    /// The names the mapping was created with, so it can be dropped after the referenced
+    /// resources are gone.
+    pub server_name: Option<String>,
+    pub role_name: Option<String>,
+}